@@ -0,0 +1,673 @@
+use crate::{
+    config::{Config, Settings},
+    console, events, update,
+    utils::parse_size,
+};
+use getopts::{Matches, Options};
+use pjsekai_soundgen_core::{
+    cache::Cache,
+    export::{ExportSettings, Format},
+    http::{HttpClient, HttpConfig},
+    i18n::{self, Lang},
+    range::TimeRange,
+    stems::StemGrouping,
+    synthesis::UnknownClipPolicy,
+    tempo::Position,
+    tr, Error,
+};
+use std::{env, fs, path::PathBuf, str::FromStr, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Render,
+    Info,
+    Timings,
+    Search,
+    Cache,
+    Effect,
+    Config,
+}
+
+impl Command {
+    pub const ALL: [Command; 7] = [
+        Command::Render,
+        Command::Info,
+        Command::Timings,
+        Command::Search,
+        Command::Cache,
+        Command::Effect,
+        Command::Config,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Render => "render",
+            Command::Info => "info",
+            Command::Timings => "timings",
+            Command::Search => "search",
+            Command::Cache => "cache",
+            Command::Effect => "effect",
+            Command::Config => "config",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|command| command.name() == name)
+    }
+
+    fn description(&self) -> String {
+        match self {
+            Command::Render => tr!(
+                "譜面から音声を生成します。（コマンドを省略した場合）",
+                "Render audio from levels. (default when no command is given)"
+            ),
+            Command::Info => tr!(
+                "譜面の情報と統計（ノーツ数、BPM変化、ノーツ密度など）を表示します。",
+                "Show level information and statistics (note counts, BPM changes, note density, etc.)."
+            ),
+            Command::Timings => tr!("効果音を鳴らすタイミングを表示します。", "Show when each sound effect is played."),
+            Command::Search => tr!("譜面を検索します。", "Search for levels."),
+            Command::Cache => tr!("キャッシュを管理します。", "Manage the cache."),
+            Command::Effect => tr!(
+                "譜面が使う効果音の一覧を表示します。-oを指定するとWAVとして書き出します。",
+                "List the sound effects used by a level. With -o, write them out as WAV files."
+            ),
+            Command::Config => {
+                tr!("設定ファイルと現在の設定を表示します。", "Show the config files and the current settings.")
+            }
+        }
+    }
+
+    fn arguments(&self) -> &'static str {
+        match self {
+            Command::Render => "[ID...]",
+            Command::Info | Command::Timings | Command::Effect => "[ID]",
+            Command::Search => "[KEYWORDS]",
+            Command::Cache => "<list|info [KEY]|prune|clear>",
+            Command::Config => "",
+        }
+    }
+
+    /// このコマンドで使えるオプション。サーバー、キャッシュ、出力先のオプションは全てのコマンドで共通です。
+    fn options(&self) -> Options {
+        let mut opts = Options::new();
+        common_options(&mut opts);
+        match self {
+            Command::Render | Command::Config => render_options(&mut opts),
+            Command::Timings => {
+                opts.optopt(
+                    "s",
+                    "shift",
+                    &tr!("SEをずらします。（秒単位）", "Shift the sound effects. (in seconds)"),
+                    "SECONDS",
+                );
+            }
+            Command::Search => {
+                opts.optopt(
+                    "",
+                    "page",
+                    &tr!("検索結果のページを指定します。（1始まり）", "Page of search results. (starting at 1)"),
+                    "PAGE",
+                );
+            }
+            Command::Info | Command::Cache | Command::Effect => {}
+        }
+        opts
+    }
+}
+
+fn common_options(opts: &mut Options) {
+    opts.optflag("h", "help", &tr!("ヘルプを表示して終了します。", "Print this help and exit."));
+    opts.optopt(
+        "o",
+        "output",
+        &tr!("出力先を指定します。（render：ファイル、複数の譜面の場合はフォルダ、info・timings：JSONファイル、effect：フォルダ）", "Output path. (render: a file, or a folder for multiple levels; info, timings: a JSON file; effect: a folder)"),
+        "OUTPUT",
+    );
+    opts.optopt(
+        "u",
+        "server",
+        &tr!(
+            "譜面を取得するSonolusサーバーのURLを指定します。",
+            "URL of the Sonolus server to fetch levels from."
+        ),
+        "URL",
+    );
+    opts.optopt("", "add-server", &tr!("サーバーを登録します。", "Register a server."), "PREFIX=URL");
+    opts.optopt(
+        "",
+        "remove-server",
+        &tr!("登録されたサーバーを削除します。", "Remove a registered server."),
+        "PREFIX",
+    );
+    opts.optflag(
+        "",
+        "list-servers",
+        &tr!("登録されたサーバーを表示して終了します。", "List the registered servers and exit."),
+    );
+    opts.optopt(
+        "",
+        "connect-timeout",
+        &tr!("接続のタイムアウトを指定します。（秒単位）", "Connection timeout. (in seconds)"),
+        "SECONDS",
+    );
+    opts.optopt(
+        "",
+        "read-timeout",
+        &tr!("読み込みのタイムアウトを指定します。（秒単位）", "Read timeout. (in seconds)"),
+        "SECONDS",
+    );
+    opts.optopt(
+        "",
+        "retries",
+        &tr!("通信に失敗したときの再試行回数を指定します。", "Number of retries when a request fails."),
+        "NUMBER",
+    );
+    opts.optopt("", "proxy", &tr!("通信に使うプロキシを指定します。", "Proxy to use for requests."), "URL");
+    opts.optmulti(
+        "H",
+        "header",
+        &tr!("通信時に追加するヘッダーを指定します。", "Extra header to send with requests."),
+        "NAME: VALUE",
+    );
+    opts.optopt("", "cache-dir", &tr!("キャッシュの保存先を指定します。", "Cache directory."), "PATH");
+    opts.optopt(
+        "",
+        "cache-max-size",
+        &tr!("キャッシュの最大サイズを指定します。（例：500M）", "Maximum cache size. (e.g. 500M)"),
+        "SIZE",
+    );
+    opts.optflag(
+        "",
+        "offline",
+        &tr!(
+            "通信せず、キャッシュのみを使います。（譜面は最後にオンラインで取得したものが使われます）",
+            "Use only the cache without connecting to the network. (levels are used as last fetched online)"
+        ),
+    );
+    opts.optflag(
+        "",
+        "verify-cache",
+        &tr!("キャッシュを使う前にハッシュを検証します。", "Verify hashes before using cached data."),
+    );
+    opts.optflag(
+        "",
+        "non-interactive",
+        &tr!(
+            "入力を求めず、譜面IDが指定されていない場合は失敗します。",
+            "Never prompt; fail if no level ID is given."
+        ),
+    );
+    opts.optflag(
+        "",
+        "json",
+        &tr!(
+            "進捗や結果を1行に1つのJSONとして出力します。（--non-interactiveを含みます）",
+            "Print progress and results as one JSON object per line. (implies --non-interactive)"
+        ),
+    );
+    opts.optopt(
+        "p",
+        "profile",
+        &tr!("設定ファイルのプロファイルを使います。", "Use a profile from the config file."),
+        "NAME",
+    );
+    opts.optopt(
+        "",
+        "lang",
+        &tr!(
+            "表示する言語を指定します。（ja、en。省略した場合はシステムの言語）",
+            "Display language. (ja or en; defaults to the system language)"
+        ),
+        "LANG",
+    );
+    opts.optflag(
+        "",
+        "no-update-check",
+        &tr!("更新を確認しません。（環境変数PJSEKAI_SOUNDGEN_NO_UPDATE_CHECKや設定ファイルのupdate-checkでも無効にできます）", "Do not check for updates. (can also be disabled with the PJSEKAI_SOUNDGEN_NO_UPDATE_CHECK environment variable or update-check in the config file)"),
+    );
+}
+
+fn render_options(opts: &mut Options) {
+    opts.optopt("b", "bgm", &tr!("BGMを上書きします。", "Replace the BGM."), "PATH");
+    opts.optopt(
+        "v",
+        "bgm-volume",
+        &tr!("BGMのボリュームを指定します。（1.0で等倍）", "BGM volume. (1.0 is unchanged)"),
+        "VOLUME",
+    );
+    opts.optopt("s", "shift", &tr!("SEをずらします。（秒単位）", "Shift the sound effects. (in seconds)"), "SECONDS");
+    opts.optflag("S", "silent", &tr!("SEのみを生成します。", "Render only the sound effects."));
+    opts.optopt(
+        "n",
+        "notes-per-thread",
+        &tr!("スレッド毎のノーツ数を指定します。", "Number of notes per thread."),
+        "NUMBER",
+    );
+    opts.optopt(
+        "f",
+        "format",
+        &tr!(
+            "出力形式を指定します。（mp3、aac、vorbis、opus、flac、wav）",
+            "Output format. (mp3, aac, vorbis, opus, flac, wav)"
+        ),
+        "FORMAT",
+    );
+    opts.optopt("", "bitrate", &tr!("ビットレートを指定します。（kbps単位）", "Bitrate. (in kbps)"), "KBPS");
+    opts.optopt(
+        "",
+        "quality",
+        &tr!("可変ビットレートの品質を指定します。（ffmpegの-q:a）", "Variable bitrate quality. (ffmpeg's -q:a)"),
+        "QUALITY",
+    );
+    opts.optopt("", "sample-rate", &tr!("サンプルレートを指定します。（Hz単位）", "Sample rate. (in Hz)"), "HZ");
+    opts.optopt(
+        "",
+        "bit-depth",
+        &tr!("ビット深度を指定します。（flac、wavのみ）", "Bit depth. (flac and wav only)"),
+        "BITS",
+    );
+    opts.optopt(
+        "",
+        "stems",
+        &tr!(
+            "効果音の種類毎とBGMのファイルも出力します。（category、critical、またはTOMLファイルのパス）",
+            "Also write separate files for each kind of sound effect and the BGM. (category, critical, or the path to a TOML file)"
+        ),
+        "MODE",
+    );
+    opts.optflag("", "no-metadata", &tr!("タグとジャケット画像を書き込みません。", "Do not write tags or cover art."));
+    opts.optopt(
+        "",
+        "from",
+        &tr!(
+            "この位置から生成します。（秒、またはb:拍、m:小節。小節は4/4拍子として数えます）",
+            "Start rendering at this position. (seconds, b:BEAT or m:MEASURE; measures are counted in 4/4)"
+        ),
+        "POSITION",
+    );
+    opts.optopt(
+        "",
+        "to",
+        &tr!(
+            "この位置まで生成します。（--fromと同じ形式）",
+            "Stop rendering at this position. (same format as --from)"
+        ),
+        "POSITION",
+    );
+    opts.optopt(
+        "",
+        "pre-roll",
+        &tr!("--fromの前に含める秒数を指定します。", "Seconds to include before --from."),
+        "SECONDS",
+    );
+    opts.optopt(
+        "",
+        "post-roll",
+        &tr!("--toの後に含める秒数を指定します。", "Seconds to include after --to."),
+        "SECONDS",
+    );
+    opts.optopt(
+        "",
+        "unknown-clip",
+        &tr!(
+            "効果音のデータに無いSEの扱いを指定します。（skip：鳴らさない、substitute：#PERFECTで代用、fail：失敗する）",
+            "What to do with clips missing from the effect data. (skip, substitute: use #PERFECT, fail)"
+        ),
+        "POLICY",
+    );
+    opts.optopt(
+        "l",
+        "list",
+        &tr!(
+            "譜面IDを1行に1つずつ書いたファイルから読み込みます。",
+            "Read level IDs from a file with one ID per line."
+        ),
+        "PATH",
+    );
+    opts.optopt(
+        "j",
+        "jobs",
+        &tr!("同時に生成する譜面の数を指定します。", "Number of levels to render at the same time."),
+        "NUMBER",
+    );
+}
+
+fn print_help(program: &str, command: Option<Command>) {
+    let Some(command) = command else {
+        println!("{}", tr!("使い方：{} [COMMAND] [OPTIONS] [ARGS]", "Usage: {} [COMMAND] [OPTIONS] [ARGS]", program));
+        println!();
+        println!("{}", tr!("コマンド：", "Commands:"));
+        for command in Command::ALL {
+            println!("  {:<10}{}", command.name(), command.description());
+        }
+        println!();
+        println!(
+            "{}",
+            tr!(
+                "各コマンドのオプションは`{} <COMMAND> --help`で確認できます。",
+                "Run `{} <COMMAND> --help` to see the options for each command.",
+                program
+            )
+        );
+        println!();
+        print!("{}", Command::Render.options().usage(&tr!("renderのオプション：", "Options for render:")));
+        return;
+    };
+    let brief = tr!(
+        "使い方：{} {} [OPTIONS] {}\n\n{}",
+        "Usage: {} {} [OPTIONS] {}\n\n{}",
+        program,
+        command.name(),
+        command.arguments(),
+        command.description()
+    );
+    print!("{}", command.options().usage(&brief));
+}
+
+/// このコマンドで定義されていないオプションは`None`として扱います。
+fn opt_str(matches: &Matches, name: &str) -> Option<String> {
+    matches.opt_defined(name).then(|| matches.opt_str(name)).flatten()
+}
+
+fn opt_present(matches: &Matches, name: &str) -> bool {
+    matches.opt_defined(name) && matches.opt_present(name)
+}
+
+/// オプションの値を`T`として読み込みます。値が不正な場合はエラーを表示して終了します。
+fn opt_parse<T: FromStr>(matches: &Matches, name: &str) -> Option<T> {
+    opt_str(matches, name).map(|s| s.parse().unwrap_or_else(|_| invalid_value(name, &s)))
+}
+
+/// 秒数のオプションを読み込みます。0以下の値は不正です。
+fn opt_duration(matches: &Matches, name: &str) -> Option<Duration> {
+    opt_str(matches, name).map(|s| {
+        s.parse::<f32>()
+            .ok()
+            .filter(|seconds| *seconds > 0.0)
+            .and_then(|seconds| Duration::try_from_secs_f32(seconds).ok())
+            .unwrap_or_else(|| invalid_value(name, &s))
+    })
+}
+
+fn invalid_value(name: &str, value: &str) -> ! {
+    console::error(&tr!("--{}の値が不正です：{}", "Invalid value for --{}: {}", name, value));
+    std::process::exit(1);
+}
+
+#[derive(Clone)]
+pub struct Args {
+    pub command: Command,
+    /// コマンド名より後ろの引数。
+    pub operands: Vec<String>,
+    pub bgm_override: Option<String>,
+    pub bgm_volume: f32,
+    pub shift: f32,
+    pub silent: bool,
+    pub output: Option<String>,
+    pub export: ExportSettings,
+    pub stems: Option<StemGrouping>,
+    pub metadata: bool,
+    pub range: Option<TimeRange>,
+    pub ids: Vec<String>,
+    pub jobs: usize,
+    pub non_interactive: bool,
+    pub notes_per_thread: usize,
+    pub unknown_clip: UnknownClipPolicy,
+    pub server: Option<String>,
+    pub add_server: Option<String>,
+    pub remove_server: Option<String>,
+    pub list_servers: bool,
+    pub page: i32,
+    pub client: HttpClient,
+    pub verify_cache: bool,
+    /// 更新を確認するかどうか。`--offline`の場合は確認しません。
+    pub update_check: bool,
+    pub cache: Cache,
+    pub config: Config,
+}
+
+/// システムのロケール、`--lang`の順に表示する言語を設定します。
+/// ヘルプの説明文も切り替えるため、`Options`を組み立てる前に引数を直接調べます。
+fn select_lang(args: &[String]) {
+    i18n::set_lang(sys_locale::get_locale().map_or(Lang::Ja, |locale| Lang::from_locale(&locale)));
+    let value = args.iter().enumerate().find_map(|(i, arg)| match arg.strip_prefix("--lang") {
+        Some("") => args.get(i + 1).cloned(),
+        Some(rest) => rest.strip_prefix('=').map(|value| value.to_string()),
+        None => None,
+    });
+    if let Some(value) = value {
+        i18n::set_lang(value.parse().unwrap_or_else(|err: Error| {
+            console::error(&err.to_string());
+            std::process::exit(1);
+        }));
+    }
+}
+
+pub fn parse_args() -> Args {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    select_lang(&args[1..]);
+    let (command, rest) = match args.get(1).and_then(|name| Command::from_name(name)) {
+        Some(command) => (Some(command), &args[2..]),
+        None => (None, &args[1..]),
+    };
+    let opts = command.unwrap_or(Command::Render).options();
+    let matches = match opts.parse(rest) {
+        Ok(m) => m,
+        Err(f) => {
+            console::error(&f.to_string());
+            print_help(&program, command);
+            std::process::exit(1);
+        }
+    };
+    if matches.opt_present("h") {
+        print_help(&program, command);
+        std::process::exit(0);
+    }
+    let command = command.unwrap_or(Command::Render);
+    let json = matches.opt_present("json");
+    events::JSON.store(json, std::sync::atomic::Ordering::SeqCst);
+    let mut config = Config::load(matches.opt_str("p").as_deref()).unwrap_or_else(|err| {
+        console::error(&err.to_string());
+        std::process::exit(1);
+    });
+    config.push_args(Settings {
+        bgm_volume: opt_parse(&matches, "bgm-volume"),
+        shift: opt_parse(&matches, "shift"),
+        silent: opt_present(&matches, "S").then_some(true),
+        notes_per_thread: opt_parse(&matches, "notes-per-thread"),
+        output: matches.opt_str("o"),
+        jobs: opt_parse(&matches, "jobs"),
+        format: opt_str(&matches, "f"),
+        bitrate: opt_str(&matches, "bitrate")
+            .map(|s| s.trim_end_matches(['k', 'K']).parse::<u32>().unwrap_or_else(|_| invalid_value("bitrate", &s))),
+        quality: opt_parse(&matches, "quality"),
+        sample_rate: opt_parse(&matches, "sample-rate"),
+        bit_depth: opt_parse(&matches, "bit-depth"),
+        stems: opt_str(&matches, "stems"),
+        unknown_clip: opt_str(&matches, "unknown-clip"),
+        update_check: matches.opt_present("no-update-check").then_some(false),
+    });
+    let settings = config.settings();
+    let operands = matches.free.clone();
+    let ids = match command {
+        Command::Render => {
+            let mut ids = operands.clone();
+            if let Some(list) = opt_str(&matches, "l") {
+                ids.extend(read_id_list(&list));
+            }
+            // 同じ譜面を2回生成すると、同じ出力先に書き込んでしまう
+            let mut seen = std::collections::HashSet::new();
+            ids.retain(|id| seen.insert(id.trim_start_matches('#').to_string()));
+            ids
+        }
+        Command::Info | Command::Timings | Command::Effect => {
+            if operands.len() > 1 {
+                console::error(&tr!(
+                    "{}には譜面IDを1つだけ指定してください。",
+                    "{} takes only one level ID.",
+                    command.name()
+                ));
+                std::process::exit(1);
+            }
+            operands.clone()
+        }
+        Command::Search | Command::Cache | Command::Config => vec![],
+    };
+    let export = if command == Command::Render {
+        export_settings(&settings, opt_present(&matches, "f"), settings.output.as_deref().filter(|_| ids.len() <= 1))
+            .unwrap_or_else(|err| {
+                console::error(&err.to_string());
+                std::process::exit(1);
+            })
+    } else {
+        ExportSettings::default()
+    };
+    let range = time_range(&matches).unwrap_or_else(|err| {
+        console::error(&err.to_string());
+        std::process::exit(1);
+    });
+    let mut cache = Cache::new(matches.opt_str("cache-dir").map(PathBuf::from).unwrap_or_else(Cache::default_dir));
+    cache.max_size = matches.opt_str("cache-max-size").map(|s| {
+        parse_size(&s).unwrap_or_else(|| {
+            console::error(&tr!("サイズが不正です：{}", "Invalid size: {}", s));
+            std::process::exit(1);
+        })
+    });
+    let default_http_config = HttpConfig::default();
+    let http_config = HttpConfig {
+        connect_timeout: opt_duration(&matches, "connect-timeout").unwrap_or(default_http_config.connect_timeout),
+        read_timeout: opt_duration(&matches, "read-timeout").unwrap_or(default_http_config.read_timeout),
+        retries: opt_parse(&matches, "retries").unwrap_or(default_http_config.retries),
+        proxy: matches.opt_str("proxy"),
+        offline: matches.opt_present("offline"),
+        headers: matches
+            .opt_strs("H")
+            .iter()
+            .map(|header| {
+                let Some((name, value)) = header.split_once(':') else {
+                    console::error(&tr!(
+                        "ヘッダーはNAME: VALUEの形式で指定してください：{}",
+                        "Headers must be in the form NAME: VALUE: {}",
+                        header
+                    ));
+                    std::process::exit(1);
+                };
+                (name.trim().to_string(), value.trim().to_string())
+            })
+            .collect(),
+        ..default_http_config
+    };
+    let client = HttpClient::new(http_config).unwrap_or_else(|err| {
+        console::error(&err.to_string());
+        std::process::exit(1);
+    });
+    Args {
+        command,
+        operands,
+        bgm_override: opt_str(&matches, "b"),
+        bgm_volume: settings.bgm_volume.unwrap(),
+        shift: settings.shift.unwrap(),
+        silent: settings.silent.unwrap(),
+        output: match command {
+            Command::Render | Command::Config => settings.output,
+            _ => matches.opt_str("o"),
+        },
+        export,
+        stems: settings.stems.as_deref().filter(|_| command == Command::Render).map(|stems| {
+            stems.parse::<StemGrouping>().unwrap_or_else(|err| {
+                console::error(&err.to_string());
+                std::process::exit(1);
+            })
+        }),
+        metadata: !opt_present(&matches, "no-metadata"),
+        range,
+        ids,
+        jobs: match settings.jobs.unwrap() {
+            0 => invalid_value("jobs", "0"),
+            jobs => jobs,
+        },
+        non_interactive: json || matches.opt_present("non-interactive"),
+        notes_per_thread: match settings.notes_per_thread.unwrap() {
+            0 => invalid_value("notes-per-thread", "0"),
+            notes_per_thread => notes_per_thread,
+        },
+        unknown_clip: settings.unknown_clip.as_deref().unwrap().parse().unwrap_or_else(|err: Error| {
+            console::error(&err.to_string());
+            std::process::exit(1);
+        }),
+        server: matches.opt_str("u"),
+        add_server: matches.opt_str("add-server"),
+        remove_server: matches.opt_str("remove-server"),
+        list_servers: matches.opt_present("list-servers"),
+        page: match opt_parse::<u32>(&matches, "page") {
+            Some(0) => invalid_value("page", "0"),
+            Some(page) => i32::try_from(page - 1).unwrap_or_else(|_| invalid_value("page", &page.to_string())),
+            None => 0,
+        },
+        client,
+        verify_cache: matches.opt_present("verify-cache"),
+        update_check: settings.update_check.unwrap() && !update::disabled_by_env() && !matches.opt_present("offline"),
+        cache,
+        config,
+    }
+}
+
+/// 出力の設定を組み立てて検証します。
+/// `--format`が無い場合は、出力先の拡張子、設定ファイルの順に形式を決めます。
+fn export_settings(settings: &Settings, format_in_args: bool, output: Option<&str>) -> anyhow::Result<ExportSettings> {
+    let format = match (&settings.format, output.and_then(Format::from_path)) {
+        (Some(format), _) if format_in_args => format.parse()?,
+        (_, Some(format)) => format,
+        (Some(format), None) => format.parse()?,
+        (None, None) => Format::Mp3,
+    };
+    let export = ExportSettings {
+        format,
+        bitrate: settings.bitrate,
+        quality: settings.quality,
+        sample_rate: settings.sample_rate,
+        bit_depth: settings.bit_depth,
+        metadata: None,
+    };
+    export.validate()?;
+    if let Some(output) = output {
+        export.validate_path(output)?;
+    }
+    Ok(export)
+}
+
+/// `--from`、`--to`、`--pre-roll`、`--post-roll`から範囲を組み立てます。どれも無い場合は`None`です。
+fn time_range(matches: &Matches) -> anyhow::Result<Option<TimeRange>> {
+    let names = ["from", "to", "pre-roll", "post-roll"];
+    if !names.iter().any(|name| opt_present(matches, name)) {
+        return Ok(None);
+    }
+    let seconds = |name: &str| -> anyhow::Result<f32> {
+        opt_str(matches, name).map_or(Ok(0.0), |s| {
+            s.parse::<f32>()
+                .map_err(|_| anyhow::Error::msg(tr!("--{}の値が不正です：{}", "Invalid value for --{}: {}", name, s)))
+        })
+    };
+    Ok(Some(TimeRange {
+        from: opt_str(matches, "from").map(|s| s.parse::<Position>()).transpose()?,
+        to: opt_str(matches, "to").map(|s| s.parse::<Position>()).transpose()?,
+        pre_roll: seconds("pre-roll")?,
+        post_roll: seconds("post-roll")?,
+    }))
+}
+
+/// 譜面IDのリストを読み込みます。空行と`//`から始まる行は無視されます。
+fn read_id_list(path: &str) -> Vec<String> {
+    let content = fs::read_to_string(path).unwrap_or_else(|err| {
+        console::error(&tr!("{}を読み込めませんでした：{}", "Could not read {}: {}", path, err));
+        std::process::exit(1);
+    });
+    content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with("//"))
+        .map(|line| line.to_string())
+        .collect()
+}
@@ -0,0 +1,454 @@
+use crate::{
+    cli::Args,
+    config::Config,
+    console, events, require_input, resolve_level, search_targets,
+    utils::{format_size, rgb},
+};
+use pjsekai_soundgen_core::{
+    export::{ExportSettings, Format},
+    get_sound_timings,
+    level::Level,
+    registry::{ServerEntry, ServerRegistry},
+    server::Server,
+    sonolus::LevelInfo,
+    sound::{CHANNELS, SAMPLE_RATE},
+    stats::LevelStats,
+    tr,
+};
+use serde_json::json;
+use std::path::Path;
+
+fn exit_on_error<T, E: std::fmt::Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|err| {
+        console::error(&err.to_string());
+        std::process::exit(1);
+    })
+}
+
+/// 結果を表示します。`-o`が指定されている場合はJSONファイルに書き出し、`--json`の場合はイベントとして出力します。
+fn report(args: &Args, command: &str, data: serde_json::Value, show: impl FnOnce()) {
+    if let Some(output) = &args.output {
+        exit_on_error(std::fs::write(output, serde_json::to_string_pretty(&data).unwrap()).map_err(|e| {
+            anyhow::Error::msg(tr!("{}に書き込めませんでした：{}", "Could not write to {}: {}", output, e))
+        }));
+        console::info(&tr!("書き出しました：{}", "Wrote {}", output));
+    } else {
+        events::report(command, data, show);
+    }
+}
+
+async fn fetch_level(args: &Args) -> Level {
+    let input = require_input(args).await;
+    let (server, name) = exit_on_error(resolve_level(args, &input, None).await);
+    console::info(&tr!(
+        "{}{}{} から譜面を取得中...",
+        "Fetching the level from {}{}{}...",
+        rgb!(server.color),
+        server.name,
+        rgb!()
+    ));
+    exit_on_error(server.fetch_level(&name).await)
+}
+
+/// `秒`を`分:秒`の形にします。
+fn format_time(seconds: f32) -> String {
+    let sign = if seconds < 0.0 { "-" } else { "" };
+    let seconds = seconds.abs();
+    format!("{}{}:{:06.3}", sign, (seconds / 60.0).floor(), seconds % 60.0)
+}
+
+pub async fn info(args: &Args) {
+    let level = fetch_level(args).await;
+    let stats = exit_on_error(LevelStats::from_level(&level).await);
+    let info = &level.info;
+    let data = json!({
+        "name": info.name,
+        "title": info.title,
+        "artists": info.artists,
+        "author": info.author,
+        "rating": info.rating,
+        "server": level.server.url,
+        "url": level.url(),
+        "entities": level.data.entities.len(),
+        "bgm": info.bgm.hash,
+        "effect": {
+            "audio": info.engine.effect.audio.hash,
+            "data": info.engine.effect.data.hash,
+        },
+        "stats": stats,
+    });
+    report(args, "info", data, || {
+        println!("{}", tr!("  タイトル：{}", "  Title: {}", info.title));
+        println!("{}", tr!("  アーティスト：{}", "  Artists: {}", info.artists));
+        println!("{}", tr!("  譜面作者：{}", "  Charter: {}", info.author));
+        println!("{}", tr!("  レベル：{}", "  Level: {}", info.rating));
+        println!("{}", tr!("  譜面ID：{}", "  Level ID: {}", info.name));
+        println!("{}", tr!("  サーバー：{} ({})", "  Server: {} ({})", level.server.name, level.server.url));
+        println!("{}", tr!("  URL：{}", "  URL: {}", level.url()));
+        println!("{}", tr!("  エンティティ数：{}", "  Entities: {}", level.data.entities.len()));
+        println!();
+        println!("{}", tr!("  ノーツ数：{}", "  Notes: {}", stats.notes));
+        println!("{}", tr!("  長さ：{}", "  Length: {}", format_time(stats.duration)));
+        println!(
+            "{}",
+            tr!(
+                "  ノーツ密度：平均 {:.2}/秒、最大 {:.0}/秒",
+                "  Note density: {:.2}/s on average, {:.0}/s at peak",
+                stats.average_nps,
+                stats.peak_nps
+            )
+        );
+        println!("{}", tr!("  スライドの合計時間：{:.3}秒", "  Total slide time: {:.3}s", stats.slide_hold_time));
+        println!("{}", tr!("  bgmOffset：{:.3}秒", "  bgmOffset: {:.3}s", stats.bgm_offset));
+
+        console::info(&tr!("アーキタイプ毎のノーツ数：", "Notes per archetype:"));
+        for (archetype, count) in stats.archetypes.iter() {
+            println!("  {:<32} {:>6}", archetype, count);
+        }
+        console::info(&tr!("効果音毎の再生回数：", "Plays per sound effect:"));
+        for (clip, count) in stats.clips.iter() {
+            println!("  {:<32} {:>6}", clip, count);
+        }
+        console::info(&tr!("BPM変化：", "BPM changes:"));
+        println!("  {:>10} {:>10} {:>10}", &tr!("拍", "Beat"), "BPM", &tr!("時刻", "Time"));
+        for change in stats.bpm_changes.iter() {
+            println!("  {:>10.3} {:>10.3} {:>10}", change.beat, change.bpm, format_time(change.time));
+        }
+    });
+}
+
+pub async fn timings(args: &Args) {
+    let level = fetch_level(args).await;
+    let timing = exit_on_error(get_sound_timings(&level, args.shift).await);
+    let mut single = timing.single.iter().collect::<Vec<_>>();
+    single.sort_by_key(|(clip, _)| clip.as_str());
+    let mut connect = timing.connect.iter().collect::<Vec<_>>();
+    connect.sort_by_key(|(clip, _)| clip.as_str());
+    let data = json!({
+        "single": timing.single,
+        "connect": timing.connect,
+    });
+    report(args, "timings", data, || {
+        for (clip, times) in single.iter() {
+            console::info(&tr!("{}（{}回）", "{} ({} times)", clip, times.len()));
+            for time in times.iter() {
+                println!("  {:>10.3}", time);
+            }
+        }
+        for (clip, ranges) in connect.iter() {
+            console::info(&tr!("{}（{}回）", "{} ({} times)", clip, ranges.len()));
+            for (start, end) in ranges.iter() {
+                println!("  {:>10.3} - {:>10.3}", start, end);
+            }
+        }
+    });
+}
+
+/// ファイル名に使えない文字を`_`に置き換えます。
+fn file_name(clip: &str) -> String {
+    clip.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+pub async fn effect(args: &Args) {
+    let level = fetch_level(args).await;
+    console::info(&tr!("効果音を読み込んでいます...", "Loading sound effects..."));
+    let effect = exit_on_error(level.server.fetch_effect(level.info.engine.effect.clone()).await);
+    let timing = exit_on_error(get_sound_timings(&level, 0.0).await);
+    let mut clips = effect.audio.iter().collect::<Vec<_>>();
+    clips.sort_by_key(|(clip, _)| clip.as_str());
+    let duration = |data: &[i16]| data.len() as f32 / CHANNELS as f32 / SAMPLE_RATE as f32;
+    let used = |clip: &str| timing.single.contains_key(clip) || timing.connect.contains_key(clip);
+
+    if let Some(output) = &args.output {
+        exit_on_error(std::fs::create_dir_all(output).map_err(|e| {
+            anyhow::Error::msg(tr!("{}を作成できませんでした：{}", "Could not create {}: {}", output, e))
+        }));
+        for (clip, sound) in clips {
+            let path = Path::new(output).join(format!("{}.wav", file_name(clip))).to_string_lossy().to_string();
+            exit_on_error(sound.clone().export(&path, &ExportSettings::new(Format::Wav)));
+            console::info(&tr!("書き出しました：{}", "Wrote {}", path));
+        }
+        return;
+    }
+    let data = json!({
+        "audio": level.info.engine.effect.audio.hash,
+        "data": level.info.engine.effect.data.hash,
+        "clips": clips
+            .iter()
+            .map(|(clip, sound)| json!({ "name": clip, "duration": duration(&sound.data), "used": used(clip) }))
+            .collect::<Vec<_>>(),
+    });
+    report(args, "effect", data, || {
+        for (clip, sound) in clips.iter() {
+            println!(
+                "{}",
+                tr!(
+                    "  {} {:<32} {:>7.3}秒",
+                    "  {} {:<32} {:>7.3}s",
+                    if used(clip) { "*" } else { " " },
+                    clip,
+                    duration(&sound.data)
+                )
+            );
+        }
+        console::info(&tr!(
+            "{}個の効果音があります。（*：この譜面で使われているもの）",
+            "{} sound effects. (*: used by this level)",
+            clips.len()
+        ));
+    });
+}
+
+pub fn level_summary(info: &LevelInfo) -> String {
+    format!("{} / {} - {} (Lv. {}) #{}", info.title, info.artists, info.author, info.rating, info.name)
+}
+
+/// 全てのサーバーでの検索に失敗した場合は終了コード1で終了します。
+pub async fn search(args: &Args, keywords: &str) {
+    let servers = search_targets(args).await;
+    let count = servers.len();
+    let mut failed = 0;
+    for server in servers {
+        console::info(&tr!("{}{}{} で検索中...", "Searching {}{}{}...", rgb!(server.color), server.name, rgb!()));
+        match server.search_levels(keywords, args.page).await {
+            Ok(response) => {
+                let data = json!({
+                    "server": server.name,
+                    "url": server.url,
+                    "page": args.page + 1,
+                    "page_count": response.page_count,
+                    "items": response.items,
+                });
+                events::report("search", data, || {
+                    if response.items.is_empty() {
+                        console::info(&tr!("譜面が見つかりませんでした。", "No levels found."));
+                        return;
+                    }
+                    for info in response.items.iter() {
+                        println!("  {}", level_summary(info));
+                    }
+                    console::info(&tr!("ページ {} / {}", "Page {} / {}", args.page + 1, response.page_count));
+                });
+            }
+            Err(err) => {
+                console::error(&err.to_string());
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 && failed == count {
+        std::process::exit(1);
+    }
+}
+
+pub async fn manage_servers(args: &Args) -> bool {
+    if args.add_server.is_none() && args.remove_server.is_none() && !args.list_servers {
+        return false;
+    }
+    let mut registry = ServerRegistry::load().unwrap_or_else(|err| {
+        console::error(&err.to_string());
+        std::process::exit(1);
+    });
+    if let Some(add_server) = &args.add_server {
+        let Some((prefix, url)) = add_server.split_once('=') else {
+            console::error(&tr!(
+                "--add-serverはPREFIX=URLの形式で指定してください。",
+                "--add-server must be in the form PREFIX=URL."
+            ));
+            std::process::exit(1);
+        };
+        let server = Server::from_url(url, &args.client).await.unwrap_or_else(|err| {
+            console::error(&err.to_string());
+            std::process::exit(1);
+        });
+        registry.insert(ServerEntry {
+            prefix: prefix.trim_end_matches('-').to_string(),
+            id: server.id,
+            name: server.name.clone(),
+            url: server.url,
+            color: server.color,
+        });
+        console::info(&tr!(
+            "{} を {}- として登録しました。",
+            "Registered {} as {}-.",
+            server.name,
+            prefix.trim_end_matches('-')
+        ));
+    }
+    if let Some(prefix) = &args.remove_server {
+        if registry.remove(prefix.trim_end_matches('-')).is_none() {
+            console::error(&tr!("{}- は登録されていません。", "{}- is not registered.", prefix));
+            std::process::exit(1);
+        }
+        console::info(&tr!("{}- を削除しました。", "Removed {}-.", prefix.trim_end_matches('-')));
+    }
+    if args.add_server.is_some() || args.remove_server.is_some() {
+        registry.save().unwrap_or_else(|err| {
+            console::error(&err.to_string());
+            std::process::exit(1);
+        });
+    }
+    if args.list_servers {
+        let data = json!({ "path": ServerRegistry::path(), "servers": registry.servers });
+        events::report("list-servers", data, || {
+            console::info(&tr!("サーバー設定：{}", "Server settings: {}", ServerRegistry::path().display()));
+            for entry in registry.servers.iter() {
+                println!("  {}{}-{}  {} ({})", rgb!(entry.color), entry.prefix, rgb!(), entry.name, entry.url);
+            }
+        });
+    }
+    true
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+pub async fn manage_cache(args: &Args, command: &[String]) {
+    let cache = &args.cache;
+    let result: anyhow::Result<()> = match command.first().map(|s| s.as_str()) {
+        Some("list") => cache
+            .entries()
+            .await
+            .map(|entries| {
+                events::report("cache-list", json!({ "entries": entries }), || {
+                    for entry in entries.iter() {
+                        println!(
+                            "  {}  {:<12} {:>10}  {:<16} {}",
+                            format_timestamp(entry.last_access),
+                            entry.kind.to_string(),
+                            format_size(entry.size),
+                            entry.server,
+                            entry.key
+                        );
+                    }
+                    console::info(&tr!("{}件のキャッシュがあります。", "{} cache entries.", entries.len()));
+                });
+            })
+            .map_err(anyhow::Error::from),
+        Some("info") => match command.get(1) {
+            Some(key) => match cache.entry(key).await {
+                Some(entry) => {
+                    let data = json!({ "entry": entry, "path": cache.path(&entry.key) });
+                    events::report("cache-info", data, || {
+                        println!("{}", tr!("  キー：{}", "  Key: {}", entry.key));
+                        println!("{}", tr!("  パス：{}", "  Path: {}", cache.path(&entry.key).display()));
+                        println!("{}", tr!("  サーバー：{}", "  Server: {}", entry.server));
+                        println!("{}", tr!("  種類：{}", "  Kind: {}", entry.kind));
+                        println!("{}", tr!("  サイズ：{}", "  Size: {}", format_size(entry.size)));
+                        println!("{}", tr!("  最終使用：{}", "  Last used: {}", format_timestamp(entry.last_access)));
+                    });
+                    Ok(())
+                }
+                None => Err(anyhow::Error::msg(tr!(
+                    "キャッシュが見つかりませんでした：{}",
+                    "Cache entry not found: {}",
+                    key
+                ))),
+            },
+            None => cache
+                .entries()
+                .await
+                .map(|entries| {
+                    let size: u64 = entries.iter().map(|entry| entry.size).sum();
+                    let data = json!({
+                        "dir": cache.dir,
+                        "count": entries.len(),
+                        "size": size,
+                        "max_size": cache.max_size,
+                    });
+                    events::report("cache-info", data, || {
+                        println!("{}", tr!("  場所：{}", "  Location: {}", cache.dir.display()));
+                        println!("{}", tr!("  件数：{}", "  Entries: {}", entries.len()));
+                        println!("{}", tr!("  合計サイズ：{}", "  Total size: {}", format_size(size)));
+                        println!(
+                            "{}",
+                            tr!(
+                                "  最大サイズ：{}",
+                                "  Maximum size: {}",
+                                cache.max_size.map(format_size).unwrap_or_else(|| tr!("無制限", "unlimited"))
+                            )
+                        );
+                    });
+                })
+                .map_err(anyhow::Error::from),
+        },
+        Some("prune") => match cache.max_size {
+            Some(max_size) => cache
+                .prune(max_size, None)
+                .await
+                .map(|removed| {
+                    console::info(&tr!(
+                        "{}件（{}）のキャッシュを削除しました。",
+                        "Removed {} cache entries ({}).",
+                        removed.len(),
+                        format_size(removed.iter().map(|entry| entry.size).sum())
+                    ));
+                })
+                .map_err(anyhow::Error::from),
+            None => Err(anyhow::Error::msg(tr!(
+                "--cache-max-sizeで最大サイズを指定してください。",
+                "Specify the maximum size with --cache-max-size."
+            ))),
+        },
+        Some("clear") => cache
+            .clear()
+            .await
+            .map(|count| {
+                console::info(&tr!("{}件のキャッシュを削除しました。", "Removed {} cache entries.", count));
+            })
+            .map_err(anyhow::Error::from),
+        _ => Err(anyhow::Error::msg(tr!(
+            "cacheの後にはlist、info、prune、clearのいずれかを指定してください。",
+            "cache must be followed by list, info, prune or clear."
+        ))),
+    };
+    if let Err(err) = result {
+        console::error(&err.to_string());
+        std::process::exit(1);
+    }
+}
+
+pub fn show_config(config: &Config) {
+    let data = json!({
+        "files": config.files.iter().map(|(path, found)| json!({ "path": path, "found": found })).collect::<Vec<_>>(),
+        "profile": config.profile,
+        "settings": config
+            .effective()
+            .into_iter()
+            .map(|(name, value, source)| json!({ "name": name, "value": value, "source": source }))
+            .collect::<Vec<_>>(),
+    });
+    events::report("config", data, || {
+        console::info(&tr!("設定ファイル：", "Config files:"));
+        for (path, found) in config.files.iter() {
+            if *found {
+                println!("  {}", path.display());
+            } else {
+                println!("{}", tr!("  {}（見つかりませんでした）", "  {} (not found)", path.display()));
+            }
+        }
+        if let Some(profile) = &config.profile {
+            console::info(&tr!("プロファイル：{}", "Profile: {}", profile));
+        }
+        console::info(&tr!("現在の設定：", "Current settings:"));
+        for (name, value, source) in config.effective() {
+            println!(
+                "  {:<18} {:<20} {}",
+                name,
+                value.unwrap_or_else(|| tr!("（未設定）", "(not set)")),
+                source.unwrap_or_default()
+            );
+        }
+    });
+}
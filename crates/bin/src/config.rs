@@ -0,0 +1,322 @@
+use anyhow::Result;
+use dirs::config_dir;
+use pjsekai_soundgen_core::tr;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// カレントディレクトリに置く、プロジェクト毎の設定ファイルの名前。
+pub static LOCAL_CONFIG_NAME: &str = "pjsekai-soundgen.toml";
+
+/// コマンドライン引数の既定値。指定されていない項目は`None`になります。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    pub bgm_volume: Option<f32>,
+    pub shift: Option<f32>,
+    pub silent: Option<bool>,
+    pub notes_per_thread: Option<usize>,
+    pub output: Option<String>,
+    pub jobs: Option<usize>,
+    pub format: Option<String>,
+    pub bitrate: Option<u32>,
+    pub quality: Option<f32>,
+    pub sample_rate: Option<u32>,
+    pub bit_depth: Option<u32>,
+    pub stems: Option<String>,
+    /// 効果音のデータに無いSEの扱い（skip、substitute、fail）。
+    pub unknown_clip: Option<String>,
+    /// `false`の場合、起動時に更新を確認しません。
+    pub update_check: Option<bool>,
+}
+
+impl Settings {
+    /// 何も指定されていないときの値。
+    pub fn builtin() -> Self {
+        Self {
+            bgm_volume: Some(1.0),
+            shift: Some(0.0),
+            silent: Some(false),
+            notes_per_thread: Some(1000),
+            output: None,
+            jobs: Some(2),
+            format: None,
+            bitrate: None,
+            quality: None,
+            sample_rate: None,
+            bit_depth: None,
+            stems: None,
+            unknown_clip: Some("substitute".to_string()),
+            update_check: Some(true),
+        }
+    }
+
+    /// `other`で指定されている項目を上書きします。
+    fn merge(&mut self, other: &Settings) {
+        macro_rules! merge {
+            ($($field:ident),*) => {
+                $(if other.$field.is_some() {
+                    self.$field = other.$field.clone();
+                })*
+            };
+        }
+        merge!(
+            bgm_volume,
+            shift,
+            silent,
+            notes_per_thread,
+            output,
+            jobs,
+            format,
+            bitrate,
+            quality,
+            sample_rate,
+            bit_depth,
+            stems,
+            unknown_clip,
+            update_check
+        );
+    }
+
+    /// 表示用に、項目名と値の組を返します。
+    pub fn fields(&self) -> Vec<(&'static str, Option<String>)> {
+        vec![
+            ("bgm-volume", self.bgm_volume.map(|v| v.to_string())),
+            ("shift", self.shift.map(|v| v.to_string())),
+            ("silent", self.silent.map(|v| v.to_string())),
+            ("notes-per-thread", self.notes_per_thread.map(|v| v.to_string())),
+            ("output", self.output.clone()),
+            ("jobs", self.jobs.map(|v| v.to_string())),
+            ("format", self.format.clone()),
+            ("bitrate", self.bitrate.map(|v| v.to_string())),
+            ("quality", self.quality.map(|v| v.to_string())),
+            ("sample-rate", self.sample_rate.map(|v| v.to_string())),
+            ("bit-depth", self.bit_depth.map(|v| v.to_string())),
+            ("stems", self.stems.clone()),
+            ("unknown-clip", self.unknown_clip.clone()),
+            ("update-check", self.update_check.map(|v| v.to_string())),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConfigFile {
+    pub defaults: Settings,
+    /// `[profiles.<name>]`で定義されたプロファイル。
+    pub profiles: BTreeMap<String, Settings>,
+}
+
+impl ConfigFile {
+    /// 設定ファイルを読み込みます。ファイルが無い場合は`None`を返します。
+    pub fn load_from(path: &Path) -> Result<Option<Self>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(anyhow::Error::msg(tr!(
+                    "設定ファイルを読み込めませんでした（{}）：{}",
+                    "Could not read the config file ({}): {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+        Self::parse(&content).map(Some).map_err(|e| {
+            anyhow::Error::msg(tr!(
+                "設定ファイルの読み込みに失敗しました（{}）：{}",
+                "Failed to parse the config file ({}): {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    pub fn parse(content: &str) -> std::result::Result<Self, toml::de::Error> {
+        let mut table: toml::Table = toml::from_str(content)?;
+        let profiles = match table.remove("profiles") {
+            Some(profiles) => profiles.try_into()?,
+            None => BTreeMap::new(),
+        };
+        Ok(Self {
+            defaults: toml::Value::Table(table).try_into()?,
+            profiles,
+        })
+    }
+}
+
+/// 設定の出どころ。
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub source: String,
+    pub settings: Settings,
+}
+
+/// ユーザー設定、プロジェクト設定、プロファイルを順に重ねた設定。
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// 読み込もうとした設定ファイルと、見つかったかどうか。
+    pub files: Vec<(PathBuf, bool)>,
+    pub profile: Option<String>,
+    pub layers: Vec<Layer>,
+}
+
+impl Config {
+    pub fn user_path() -> PathBuf {
+        let mut path = config_dir().unwrap_or_else(|| PathBuf::from("./config"));
+        path.push("pjsekai-soundgen-rust");
+        path.push("config.toml");
+        path
+    }
+
+    pub fn local_path() -> PathBuf {
+        PathBuf::from(LOCAL_CONFIG_NAME)
+    }
+
+    pub fn load(profile: Option<&str>) -> Result<Self> {
+        Self::load_from(&[Self::user_path(), Self::local_path()], profile)
+    }
+
+    /// 設定ファイルを順に読み込みます。後のファイルの設定が優先されます。
+    /// プロファイルは全てのファイルの既定値より優先されます。
+    pub fn load_from(paths: &[PathBuf], profile: Option<&str>) -> Result<Self> {
+        let mut config = Config {
+            profile: profile.map(|profile| profile.to_string()),
+            files: vec![],
+            layers: vec![Layer {
+                source: tr!("既定値", "default"),
+                settings: Settings::builtin(),
+            }],
+        };
+        let mut files = vec![];
+        for path in paths {
+            let file = ConfigFile::load_from(path)?;
+            config.files.push((path.clone(), file.is_some()));
+            if let Some(file) = file {
+                files.push((path, file));
+            }
+        }
+        for (path, file) in files.iter() {
+            config.layers.push(Layer {
+                source: path.display().to_string(),
+                settings: file.defaults.clone(),
+            });
+        }
+        if let Some(profile) = profile {
+            let mut found = false;
+            for (path, file) in files.iter() {
+                if let Some(settings) = file.profiles.get(profile) {
+                    found = true;
+                    config.layers.push(Layer {
+                        source: format!("{} [profiles.{}]", path.display(), profile),
+                        settings: settings.clone(),
+                    });
+                }
+            }
+            if !found {
+                let available = files
+                    .iter()
+                    .flat_map(|(_, file)| file.profiles.keys().cloned())
+                    .collect::<std::collections::BTreeSet<_>>();
+                return Err(anyhow::Error::msg(tr!(
+                    "プロファイルが見つかりませんでした：{}（利用可能：{}）",
+                    "Profile not found: {} (available: {})",
+                    profile,
+                    if available.is_empty() {
+                        tr!("なし", "none")
+                    } else {
+                        available.into_iter().collect::<Vec<_>>().join(", ")
+                    }
+                )));
+            }
+        }
+        Ok(config)
+    }
+
+    /// 最も優先される設定として、コマンドライン引数を重ねます。
+    pub fn push_args(&mut self, settings: Settings) {
+        self.layers.push(Layer {
+            source: tr!("コマンドライン引数", "command line"),
+            settings,
+        });
+    }
+
+    /// 項目毎に、値とその値を決めた設定の出どころを返します。
+    pub fn effective(&self) -> Vec<(&'static str, Option<String>, Option<&str>)> {
+        let mut effective =
+            Settings::builtin().fields().into_iter().map(|(name, _)| (name, None, None)).collect::<Vec<_>>();
+        for layer in self.layers.iter() {
+            for (entry, (_, value)) in effective.iter_mut().zip(layer.settings.fields()) {
+                if value.is_some() {
+                    entry.1 = value;
+                    entry.2 = Some(layer.source.as_str());
+                }
+            }
+        }
+        effective
+    }
+
+    pub fn settings(&self) -> Settings {
+        let mut settings = Settings::default();
+        for layer in self.layers.iter() {
+            settings.merge(&layer.settings);
+        }
+        settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn layers_files_profiles_and_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = write(
+            dir.path(),
+            "config.toml",
+            "bgm-volume = 0.5\nshift = 1.0\njobs = 3\n\n[profiles.loud]\noutput = \"loud.mp3\"\n",
+        );
+        let missing = dir.path().join("missing.toml");
+        let local = write(dir.path(), LOCAL_CONFIG_NAME, "shift = 2.0\n\n[profiles.loud]\nbgm-volume = 2.0\n");
+
+        let mut config = Config::load_from(&[user.clone(), missing.clone(), local.clone()], Some("loud")).unwrap();
+        config.push_args(Settings {
+            jobs: Some(8),
+            ..Settings::default()
+        });
+
+        assert_eq!(config.files, [(user.clone(), true), (missing, false), (local.clone(), true)]);
+        let settings = config.settings();
+        assert_eq!(settings.bgm_volume, Some(2.0));
+        assert_eq!(settings.shift, Some(2.0));
+        assert_eq!(settings.jobs, Some(8));
+        assert_eq!(settings.output.as_deref(), Some("loud.mp3"));
+        assert_eq!(settings.notes_per_thread, Settings::builtin().notes_per_thread);
+
+        let effective = config.effective();
+        let source = |name: &str| effective.iter().find(|(field, _, _)| *field == name).unwrap().2.unwrap();
+        assert_eq!(source("bgm-volume"), format!("{} [profiles.loud]", local.display()));
+        assert_eq!(source("output"), format!("{} [profiles.loud]", user.display()));
+        assert_eq!(source("shift"), local.display().to_string());
+        assert_eq!(source("jobs"), config.layers.last().unwrap().source);
+        assert_eq!(source("notes-per-thread"), config.layers[0].source);
+    }
+
+    #[test]
+    fn rejects_unknown_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let local = write(dir.path(), LOCAL_CONFIG_NAME, "[profiles.loud]\nbgm-volume = 2.0\n");
+
+        let error = Config::load_from(&[local], Some("quiet")).unwrap_err().to_string();
+
+        assert!(error.contains("quiet") && error.contains("loud"), "{}", error);
+    }
+}
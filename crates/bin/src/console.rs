@@ -1,7 +1,11 @@
 #![allow(dead_code)]
 
-use crate::utils::*;
+use crate::{
+    events::{self, Event},
+    utils::*,
+};
 use once_cell::sync::Lazy;
+use pjsekai_soundgen_core::tr;
 use regex::Regex;
 use std::sync::atomic::AtomicBool;
 
@@ -9,16 +13,24 @@ pub static ANSI: AtomicBool = AtomicBool::new(false);
 pub static ANSI_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap());
 
 pub fn show_title() {
-    let messages = vec![
+    if events::enabled() {
+        return;
+    }
+    let messages = [
         format!(
             "{}== pjsekai-soundgen-rust ------------------------------------------------------{}",
             rgb!(0x00b5c9),
             rgb!()
         ),
-        format!("    {}pjsekai-soundgen-rust / Rust版プロセカ風譜面音声生成ツール{}", rgb!(0x00afc7), rgb!()),
+        tr!(
+            "    {}pjsekai-soundgen-rust / Rust版プロセカ風譜面音声生成ツール{}",
+            "    {}pjsekai-soundgen-rust / Project Sekai style level audio generator in Rust{}",
+            rgb!(0x00afc7),
+            rgb!()
+        ),
         format!("    Version: {}{}{}", rgb!(0x0f6ea3), env!("CARGO_PKG_VERSION"), rgb!()),
         format!("    Developed by {}名無し｡(@sevenc-nanashi){}", rgb!(0x48b0d5), rgb!()),
-        format!("    https://github.com/sevenc-nanashi/pjsekai-soundgen-rust"),
+        "    https://github.com/sevenc-nanashi/pjsekai-soundgen-rust".to_string(),
         format!(
             "{}-------------------------------------------------------------------------------{}",
             rgb!(0xff5a91),
@@ -44,14 +56,32 @@ pub fn colored_log(prefix: &str, msg: &str, escape_code: &str) {
 }
 
 pub fn error(msg: &str) {
+    if events::enabled() {
+        return events::emit(Event::Error {
+            target: None,
+            message: &console::strip_ansi_codes(msg),
+        });
+    }
     colored_log("X", msg, "\x1b[31m");
 }
 
 pub fn warning(msg: &str) {
+    if events::enabled() {
+        return events::emit(Event::Warning {
+            target: None,
+            message: &console::strip_ansi_codes(msg),
+        });
+    }
     colored_log("!", msg, "\x1b[33m");
 }
 
 pub fn info(msg: &str) {
+    if events::enabled() {
+        return events::emit(Event::Info {
+            target: None,
+            message: &console::strip_ansi_codes(msg),
+        });
+    }
     colored_log("i", msg, "\x1b[36m");
 }
 
@@ -0,0 +1,145 @@
+use serde::Serialize;
+use std::{
+    io::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// `--json`が指定されたとき、進捗を1行に1つのJSONとして出力します。
+pub static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn enabled() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    FetchLevel,
+    Bgm,
+    Timing,
+    Effect,
+    Synthesis,
+    Export,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhaseState {
+    Start,
+    Finish,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThreadEvent<'a> {
+    pub id: &'a str,
+    pub max: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResultEvent<'a> {
+    pub target: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `target`は、複数の譜面を生成しているときにどの譜面のイベントかを表します。
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event<'a> {
+    Info {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a str>,
+        message: &'a str,
+    },
+    Warning {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a str>,
+        message: &'a str,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a str>,
+        message: &'a str,
+    },
+    Phase {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a str>,
+        phase: Phase,
+        state: PhaseState,
+    },
+    Level {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a str>,
+        name: &'a str,
+        title: &'a str,
+        artists: &'a str,
+        author: &'a str,
+        rating: i32,
+    },
+    Download {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a str>,
+        id: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        label: Option<&'a str>,
+        state: PhaseState,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        total: Option<u64>,
+    },
+    SynthesisStart {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a str>,
+        threads: Vec<ThreadEvent<'a>>,
+    },
+    Progress {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a str>,
+        thread: &'a str,
+        current: i32,
+    },
+    ThreadFinish {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a str>,
+        thread: &'a str,
+    },
+    Output {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a str>,
+        /// ステムの場合はステム名。
+        #[serde(skip_serializing_if = "Option::is_none")]
+        stem: Option<&'a str>,
+        path: &'a str,
+    },
+    /// `info`などのコマンドの結果。
+    Report {
+        command: &'a str,
+        data: &'a serde_json::Value,
+    },
+    Summary {
+        succeeded: usize,
+        failed: usize,
+        results: Vec<ResultEvent<'a>>,
+    },
+}
+
+/// イベントを標準出力に書き出します。`--json`が指定されていない場合は何もしません。
+pub fn emit(event: Event) {
+    if !enabled() {
+        return;
+    }
+    let line = serde_json::to_string(&event).expect("イベントのシリアライズに失敗しました");
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", line);
+    let _ = stdout.flush();
+}
+
+/// `--json`の場合は結果を`Report`イベントとして出力し、そうでない場合は`show`で表示します。
+pub fn report(command: &str, data: serde_json::Value, show: impl FnOnce()) {
+    if enabled() {
+        emit(Event::Report { command, data: &data });
+    } else {
+        show();
+    }
+}
@@ -1,215 +1,188 @@
+mod cli;
+mod commands;
+mod config;
 mod console;
+mod events;
+mod progress;
+mod render;
+mod update;
 mod utils;
 
-use crate::{console::show_title, utils::rgb};
-use dialoguer::{theme::ColorfulTheme, Input};
-use getopts::Options;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use octocrab::Octocrab;
-use pjsekai_soundgen_core::{server::Server, sound::Sound, synthesis::Progress};
-use std::{
-    collections::HashMap,
-    io::ErrorKind,
-    thread, {env, fs},
-};
-use tokio::{
-    fs::File,
-    io::{AsyncReadExt, AsyncWriteExt},
+use crate::{
+    cli::{parse_args, Args, Command},
+    console::show_title,
 };
+use dialoguer::{theme::ColorfulTheme, Input, Select};
+use pjsekai_soundgen_core::{identifier::LevelIdentifier, registry::ServerRegistry, server::Server, tr};
+use std::{fs, io::ErrorKind};
 
-static LOG_STYLE: &str = "[{elapsed_precise} / {eta_precise}] [{bar:50.{color_fg}/{color_bg}}] {pos:>7}/{len:7} {msg}";
+async fn server_from_url(
+    args: &Args,
+    registry: &ServerRegistry,
+    url: &str,
+    target: Option<&str>,
+) -> anyhow::Result<Server> {
+    Ok(match registry.find_by_url(url) {
+        Some(entry) => configure_server(args, entry.to_server(), target),
+        None => configure_server(args, Server::from_url(url, &args.client).await?, target),
+    })
+}
 
-struct Args {
-    bgm_override: Option<String>,
-    bgm_volume: f32,
-    shift: f32,
-    silent: bool,
-    output: Option<String>,
-    id: Option<String>,
-    notes_per_thread: usize,
+/// `target`はダウンロードの進捗に付ける、生成中の譜面の名前です。
+fn configure_server(args: &Args, server: Server, target: Option<&str>) -> Server {
+    let mut server = server
+        .with_client(args.client.clone())
+        .with_cache(args.cache.clone())
+        .with_download_progress(progress::download_sender(target));
+    server.verify_cache = args.verify_cache;
+    server
 }
 
-fn parse_args() -> Args {
-    let mut opts = Options::new();
-    opts.optflag("h", "help", "ヘルプを表示して終了します。");
-    opts.optopt("b", "bgm", "BGMを上書きします。", "PATH");
-    opts.optopt("v", "bgm-volume", "BGMのボリュームを指定します。（1.0で等倍）", "VOLUME");
-    opts.optopt("s", "shift", "SEをずらします。（秒単位）", "SECONDS");
-    opts.optflag("S", "silent", "SEのみを生成します。");
-    opts.optopt("n", "notes-per-thread", "スレッド毎のノーツ数を指定します。", "NUMBER");
-    opts.optopt("o", "output", "出力先を指定します。", "OUTPUT");
-    let matches = match opts.parse(env::args().collect::<Vec<_>>()) {
-        Ok(m) => m,
-        Err(f) => {
-            println!("{}", f);
-            println!("{}", opts.usage(""));
-            std::process::exit(1);
-        }
+async fn resolve_level(args: &Args, input: &str, target: Option<&str>) -> anyhow::Result<(Server, String)> {
+    let registry = ServerRegistry::load()?;
+    let identifier = LevelIdentifier::parse(input, &registry)?;
+    let server = match &args.server {
+        Some(url) => server_from_url(args, &registry, url, target).await?,
+        None => configure_server(args, identifier.resolve_server(&registry, &args.client).await?, target),
     };
-    if matches.opt_present("h") {
-        let args: Vec<String> = env::args().collect();
-        println!("{}", opts.usage(format!("{} [OPTIONS] [ID]", &args[0]).as_str()));
-        std::process::exit(0);
-    }
-    Args {
-        bgm_override: matches.opt_str("b"),
-        bgm_volume: matches.opt_str("v").map(|s| s.parse::<f32>().unwrap()).unwrap_or(1.0),
-        shift: matches.opt_str("s").map(|s| s.parse::<f32>().unwrap()).unwrap_or(0.0),
-        silent: matches.opt_present("S"),
-        output: matches.opt_str("o"),
-        id: matches.free.get(1).map(|s| s.to_string()),
-        notes_per_thread: matches.opt_str("n").map(|s| s.parse::<usize>().unwrap()).unwrap_or(1000),
-    }
+    Ok((server, identifier.name))
 }
 
-async fn should_check_update() -> bool {
-    let executable_path = process_path::get_executable_path().unwrap();
-    let flag_path = executable_path.parent().unwrap().join(".update-check");
-    if !flag_path.exists() {
-        return true;
-    }
-    let flag = fs::read_to_string(flag_path).unwrap();
-    let now = chrono::Local::now();
-    let last_checked = chrono::DateTime::parse_from_rfc3339(flag.as_str()).unwrap();
-    if now.signed_duration_since(last_checked).num_days() >= 1 {
-        return true;
+async fn search_targets(args: &Args) -> Vec<Server> {
+    let registry = ServerRegistry::load().unwrap_or_else(|err| {
+        console::error(&err.to_string());
+        std::process::exit(1);
+    });
+    match &args.server {
+        Some(url) => vec![server_from_url(args, &registry, url, None).await.unwrap_or_else(|err| {
+            console::error(&err.to_string());
+            std::process::exit(1);
+        })],
+        None => registry.servers.iter().map(|entry| configure_server(args, entry.to_server(), None)).collect(),
     }
-    false
 }
 
-async fn check_update() {
-    let executable_path = process_path::get_executable_path().unwrap();
-    let flag_path = executable_path.parent().unwrap().join(".update-check");
-    let mut file = File::create(flag_path).await.unwrap();
-    let now = chrono::Local::now();
-    file.write_all(now.to_rfc3339().as_bytes()).await.unwrap();
-    let octocrab = Octocrab::builder().build().unwrap();
-    let release = octocrab.repos("sevenc-nanashi", "pjsekai-soundgen-rust").releases().get_latest().await.unwrap();
-    let version = release.tag_name.trim_start_matches('v');
-    let current_version = env!("CARGO_PKG_VERSION");
-    if version != current_version {
-        console::info(&format!("新しいバージョンがリリースされています：v{} -> v{}", current_version, version));
-        console::info(&format!("ダウンロード：{}", release.html_url));
-    }
-}
+async fn pick_level(args: &Args) -> String {
+    let theme = ColorfulTheme::default();
+    let mode = Select::with_theme(&theme)
+        .with_prompt(&tr!("譜面の指定方法を選択してください。", "How do you want to choose a level?"))
+        .items(&[
+            &tr!("譜面IDまたはURLを入力", "Enter a level ID or URL"),
+            &tr!("キーワードで検索", "Search by keywords"),
+        ])
+        .default(0)
+        .interact()
+        .unwrap();
+    if mode == 0 {
+        console::ask(&tr!(
+            "譜面IDをプレフィックス込みで、または譜面のURLを入力してください。",
+            "Enter a level ID including its prefix, or a level URL."
+        ));
 
-#[tokio::main]
-async fn main() {
-    let ansi = enable_ansi_support::enable_ansi_support().is_ok();
-    console::ANSI.store(ansi, std::sync::atomic::Ordering::SeqCst);
-    show_title();
-    if should_check_update().await {
-        check_update().await;
-    }
-    let args = parse_args();
-    if args.output.is_none() {
-        fs::create_dir("./dist").unwrap_or_else(|err| {
-            if err.kind() != ErrorKind::AlreadyExists {
-                console::error("distフォルダを作成できませんでした。");
-                std::process::exit(1);
-            }
-        });
+        return Input::<String>::with_theme(&theme).allow_empty(false).with_prompt("").interact().unwrap();
     }
-    let name = if args.id.is_none() {
-        console::ask("譜面IDをプレフィックス込みで入力してください。");
 
-        Input::<String>::with_theme(&ColorfulTheme::default())
-            .allow_empty(false)
-            .with_prompt("")
-            .interact()
-            .unwrap()
-            .trim_start_matches('#')
-            .to_string()
+    let mut servers = search_targets(args).await;
+    let server = if servers.len() == 1 {
+        servers.remove(0)
     } else {
-        args.id.unwrap().trim_start_matches('#').to_string()
+        let index = Select::with_theme(&theme)
+            .with_prompt(&tr!("検索するサーバーを選択してください。", "Choose a server to search."))
+            .items(&servers.iter().map(|server| server.name.as_str()).collect::<Vec<_>>())
+            .default(0)
+            .interact()
+            .unwrap();
+        servers.remove(index)
     };
-    let server = Server::guess(&name).unwrap_or_else(|e| {
-        console::error(&e.to_string());
-        std::process::exit(1);
-    });
-
-    console::info(&format!("{}{}{} から譜面を取得中...", rgb!(server.color), server.name, rgb!()));
-    let level = server.fetch_level(&name).await.unwrap_or_else(|err| {
-        console::error(&err.to_string());
-        std::process::exit(1);
-    });
-    console::info(&format!(
-        "{} / {} - {} (Lv. {}) が選択されました。",
-        level.info.title, level.info.artists, level.info.author, level.info.rating
-    ));
+    console::ask(&tr!("キーワードを入力してください。", "Enter keywords."));
+    let keywords = Input::<String>::with_theme(&theme).allow_empty(true).with_prompt("").interact().unwrap();
 
-    console::info("BGMを読み込んでいます...");
-    let mut bgm_buf: Vec<u8> = Vec::new();
-    if args.bgm_override.is_some() {
-        let mut file = File::open(args.bgm_override.unwrap()).await.expect("ファイルを開けませんでした。");
-        file.read_to_end(&mut bgm_buf).await.unwrap();
-    } else {
-        level.fetch_bgm(&mut bgm_buf).await.unwrap_or_else(|err| {
+    let mut page = args.page;
+    loop {
+        let response = server.search_levels(&keywords, page).await.unwrap_or_else(|err| {
             console::error(&err.to_string());
             std::process::exit(1);
         });
-    }
-    let bgm = Sound::load(&bgm_buf) * args.bgm_volume;
-
-    console::info("譜面を読み込んでいます...");
-    let timing = match pjsekai_soundgen_core::get_sound_timings(&level, args.shift).await {
-        Ok(t) => t,
-        Err(err) => {
-            console::error(&err.to_string());
+        if response.items.is_empty() {
+            console::error(&tr!("譜面が見つかりませんでした。", "No levels found."));
             std::process::exit(1);
         }
-    };
+        let mut items = response.items.iter().map(commands::level_summary).collect::<Vec<_>>();
+        let next_index = (page + 1 < response.page_count).then(|| {
+            items.push(tr!("次のページ →", "Next page →"));
+            items.len() - 1
+        });
+        let prev_index = (page > 0).then(|| {
+            items.push(tr!("← 前のページ", "← Previous page"));
+            items.len() - 1
+        });
+        let selected = Select::with_theme(&theme)
+            .with_prompt(tr!(
+                "譜面を選択してください。（{} / {}）",
+                "Choose a level. ({} / {})",
+                page + 1,
+                response.page_count
+            ))
+            .items(&items)
+            .default(0)
+            .interact()
+            .unwrap();
+        if Some(selected) == next_index {
+            page += 1;
+        } else if Some(selected) == prev_index {
+            page -= 1;
+        } else {
+            return response.items[selected].name.clone();
+        }
+    }
+}
 
-    console::info("効果音を読み込んでいます...");
-    let effect = server.fetch_effect(level.info.engine.effect).await.unwrap_or_else(|err| {
-        console::error(&err.to_string());
-        std::process::exit(1);
-    });
+#[tokio::main]
+async fn main() {
+    let ansi = enable_ansi_support::enable_ansi_support().is_ok();
+    console::ANSI.store(ansi, std::sync::atomic::Ordering::SeqCst);
+    let args = parse_args();
+    show_title();
+    let update = args.update_check.then(update::spawn).flatten();
+    if commands::manage_servers(&args).await {
+        return;
+    }
+    match args.command {
+        Command::Render => render(&args).await,
+        Command::Info => commands::info(&args).await,
+        Command::Timings => commands::timings(&args).await,
+        Command::Effect => commands::effect(&args).await,
+        Command::Search => commands::search(&args, &args.operands.join(" ")).await,
+        Command::Cache => commands::manage_cache(&args, &args.operands).await,
+        Command::Config => commands::show_config(&args.config),
+    }
+    update::finish(update).await;
+}
 
-    let progresses = MultiProgress::new();
-    let mut progresses_map: HashMap<String, ProgressBar> = HashMap::new();
-    let style = ProgressStyle::default_bar().progress_chars("- ");
-    let rx = pjsekai_soundgen_core::synthesis(&timing, &effect, args.notes_per_thread).await;
-    let Progress::Info { threads } = rx.recv().unwrap() else {
-        unreachable!()
-    };
-    console::info(format!("{}スレッドで合成を開始します。", threads.len()).as_str());
-    for (name, info) in threads.iter() {
-        let progress =
-            ProgressBar::new(info.max as u64)
-                .with_style(style.clone().template(
-                    LOG_STYLE.replace("{color_fg}", info.color.fg).replace("{color_bg}", info.color.bg).as_str(),
-                ))
-                .with_message(name.clone());
-        progresses.add(progress.clone());
-        progresses_map.insert(name.clone(), progress);
+/// 譜面IDが指定されていない場合は、入力を求めます。
+async fn require_input(args: &Args) -> String {
+    if let Some(id) = args.ids.first() {
+        return id.clone();
     }
-    let draw_thread = thread::spawn(move || progresses.join().unwrap());
-    let mut merged_sounds = Sound::empty(None);
-    while !progresses_map.is_empty() {
-        match rx.recv().unwrap() {
-            Progress::Update { id, current } => {
-                progresses_map.get(&id).unwrap().set_position(current as u64);
-            }
-            Progress::Finish { id, sound } => {
-                progresses_map.get(&id).unwrap().finish();
-                merged_sounds = merged_sounds.overlay_at(&sound, 0.0);
-                progresses_map.remove(&id);
+    if args.non_interactive {
+        console::error(&tr!("譜面IDが指定されていません。", "No level ID was given."));
+        std::process::exit(1);
+    }
+    pick_level(args).await
+}
+
+async fn render(args: &Args) {
+    if args.output.is_none() {
+        fs::create_dir("./dist").unwrap_or_else(|err| {
+            if err.kind() != ErrorKind::AlreadyExists {
+                console::error(&tr!("distフォルダを作成できませんでした。", "Could not create the dist folder."));
+                std::process::exit(1);
             }
-            _ => unreachable!(),
-        }
+        });
     }
-    draw_thread.join().unwrap();
-    console::info("合成が完了しました。");
-    let mut final_bgm: Sound;
-    if args.silent {
-        final_bgm = Sound::empty(None);
+    if args.ids.len() > 1 {
+        render::render_batch(args, args.ids.clone()).await;
     } else {
-        final_bgm = bgm;
+        render::render_one(args, &require_input(args).await).await;
     }
-    final_bgm = final_bgm.overlay_at(&merged_sounds, 0.0);
-    let output = args.output.unwrap_or(format!("dist/{}.mp3", name));
-    console::info("出力しています...");
-    final_bgm.export(output.as_str());
-    console::info(format!("完了しました：{}", output).as_str());
 }
@@ -0,0 +1,108 @@
+use crate::events::{self, Event, PhaseState};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use once_cell::sync::Lazy;
+use pjsekai_soundgen_core::http::DownloadProgress;
+use std::{
+    collections::HashMap,
+    sync::mpsc::{channel, Sender},
+    thread,
+};
+
+pub static LOG_STYLE: &str =
+    "[{elapsed_precise} / {eta_precise}] [{bar:50.{color_fg}/{color_bg}}] {pos:>7}/{len:7} {msg}";
+static DOWNLOAD_STYLE: &str =
+    "[{elapsed_precise} / {eta_precise}] [{bar:50.cyan/blue}] {bytes:>10}/{total_bytes:10} {msg}";
+static DOWNLOAD_SPINNER_STYLE: &str = "[{elapsed_precise}] {spinner:.cyan} {bytes:>10} {msg}";
+
+pub fn bar_style(color_fg: &str, color_bg: &str) -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template(&LOG_STYLE.replace("{color_fg}", color_fg).replace("{color_bg}", color_bg))
+        .unwrap()
+        .progress_chars("- ")
+}
+
+/// ダウンロードと合成のプログレスバーを同じ場所に表示するための`MultiProgress`。
+/// `--json`が指定されている場合は何も表示しません。
+pub static PROGRESSES: Lazy<MultiProgress> = Lazy::new(|| {
+    if events::enabled() {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    }
+});
+
+/// ダウンロードの進捗と、その譜面の名前を受け取る`Sender`。
+static DOWNLOAD_SENDER: Lazy<Sender<(Option<String>, DownloadProgress)>> = Lazy::new(|| {
+    let (tx, rx) = channel::<(Option<String>, DownloadProgress)>();
+    thread::spawn(move || {
+        let mut bars: HashMap<String, ProgressBar> = HashMap::new();
+        for (target, progress) in rx {
+            emit_download(target.as_deref(), &progress);
+            match progress {
+                DownloadProgress::Start { id, label, total } => {
+                    let bar = match total {
+                        Some(total) => ProgressBar::new(total).with_style(
+                            ProgressStyle::default_bar().template(DOWNLOAD_STYLE).unwrap().progress_chars("- "),
+                        ),
+                        None => ProgressBar::new_spinner()
+                            .with_style(ProgressStyle::default_spinner().template(DOWNLOAD_SPINNER_STYLE).unwrap()),
+                    };
+                    let label = match &target {
+                        Some(target) => format!("[{}] {}", target, label),
+                        None => label,
+                    };
+                    if let Some(old) = bars.insert(id, PROGRESSES.add(bar.with_message(label))) {
+                        old.finish_and_clear();
+                    }
+                }
+                DownloadProgress::Update { id, downloaded } => {
+                    if let Some(bar) = bars.get(&id) {
+                        bar.set_position(downloaded);
+                    }
+                }
+                DownloadProgress::Finish { id } => {
+                    if let Some(bar) = bars.remove(&id) {
+                        bar.finish();
+                    }
+                }
+            }
+        }
+    });
+    tx
+});
+
+fn emit_download(target: Option<&str>, progress: &DownloadProgress) {
+    match progress {
+        DownloadProgress::Start { id, label, total } => events::emit(Event::Download {
+            target,
+            id,
+            label: Some(label),
+            state: PhaseState::Start,
+            total: *total,
+        }),
+        DownloadProgress::Finish { id } => events::emit(Event::Download {
+            target,
+            id,
+            label: None,
+            state: PhaseState::Finish,
+            total: None,
+        }),
+        DownloadProgress::Update { .. } => {}
+    }
+}
+
+/// ダウンロードの進捗をプログレスバーとして表示する`Sender`を返します。
+/// `target`を指定すると、進捗に生成中の譜面の名前を付けます。
+pub fn download_sender(target: Option<&str>) -> Sender<DownloadProgress> {
+    let (tx, rx) = channel::<DownloadProgress>();
+    let target = target.map(str::to_string);
+    let sender = DOWNLOAD_SENDER.clone();
+    thread::spawn(move || {
+        for progress in rx {
+            if sender.send((target.clone(), progress)).is_err() {
+                break;
+            }
+        }
+    });
+    tx
+}
@@ -0,0 +1,414 @@
+use crate::{
+    cli::Args,
+    console,
+    events::{self, Event, Phase, PhaseState, ResultEvent, ThreadEvent},
+    progress, resolve_level,
+    utils::rgb,
+};
+use indicatif::ProgressBar;
+use pjsekai_soundgen_core::{
+    export::Metadata,
+    pipeline::{self, BgmSource, EffectStore, PipelineEvent, PipelineOptions, Prepared, Stage},
+    sound::Sound,
+    synthesis::Progress,
+    tr,
+};
+use std::{collections::HashMap, path::Path, sync::Arc};
+use tokio::sync::Semaphore;
+
+struct Logger {
+    label: Option<String>,
+}
+
+impl Logger {
+    fn format(&self, msg: &str) -> String {
+        match &self.label {
+            Some(label) => format!("[{}] {}", label, msg),
+            None => msg.to_string(),
+        }
+    }
+
+    fn target(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    fn info(&self, msg: &str) {
+        if events::enabled() {
+            events::emit(Event::Info {
+                target: self.target(),
+                message: &::console::strip_ansi_codes(msg),
+            });
+        } else {
+            console::info(&self.format(msg));
+        }
+    }
+
+    fn warning(&self, msg: &str) {
+        if events::enabled() {
+            events::emit(Event::Warning {
+                target: self.target(),
+                message: &::console::strip_ansi_codes(msg),
+            });
+        } else {
+            console::warning(&self.format(msg));
+        }
+    }
+
+    fn error(&self, msg: &str) {
+        if events::enabled() {
+            events::emit(Event::Error {
+                target: self.target(),
+                message: &::console::strip_ansi_codes(msg),
+            });
+        } else {
+            console::error(&self.format(msg));
+        }
+    }
+
+    fn phase(&self, phase: Phase, state: PhaseState) {
+        events::emit(Event::Phase {
+            target: self.target(),
+            phase,
+            state,
+        });
+    }
+}
+
+fn output_path(args: &Args, name: &str, batch: bool) -> String {
+    let file_name = format!("{}.{}", name, args.export.format.extension());
+    match (&args.output, batch) {
+        (Some(output), true) => Path::new(output).join(file_name).to_string_lossy().to_string(),
+        (Some(output), false) => output.clone(),
+        (None, _) => format!("dist/{}", file_name),
+    }
+}
+
+/// `dist/name.mp3`に対して`dist/name.taps.mp3`のような、ステムの出力先を返します。
+fn stem_path(output: &str, stem: &str) -> String {
+    let path = Path::new(output);
+    let extension = path.extension().map(|ext| ext.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_extension(format!("{}.{}", stem, extension)).to_string_lossy().to_string()
+}
+
+/// 譜面を1つ生成し、出力先を返します。
+async fn render(args: &Args, input: &str, effects: &EffectStore, batch: bool) -> anyhow::Result<String> {
+    let logger = Logger {
+        label: batch.then(|| input.to_string()),
+    };
+    logger.phase(Phase::FetchLevel, PhaseState::Start);
+    let (server, name) = resolve_level(args, input, logger.target()).await?;
+
+    logger.info(&tr!(
+        "{}{}{} から譜面を取得中...",
+        "Fetching the level from {}{}{}...",
+        rgb!(server.color),
+        server.name,
+        rgb!()
+    ));
+    let level = server.fetch_level(&name).await?;
+    logger.phase(Phase::FetchLevel, PhaseState::Finish);
+    logger.info(&tr!(
+        "{} / {} - {} (Lv. {}) が選択されました。",
+        "Selected {} / {} - {} (Lv. {}).",
+        level.info.title,
+        level.info.artists,
+        level.info.author,
+        level.info.rating
+    ));
+    events::emit(Event::Level {
+        target: logger.target(),
+        name: &level.info.name,
+        title: &level.info.title,
+        artists: &level.info.artists,
+        author: &level.info.author,
+        rating: level.info.rating,
+    });
+
+    let bgm = if args.silent {
+        BgmSource::None
+    } else if let Some(bgm_override) = &args.bgm_override {
+        BgmSource::Data(tokio::fs::read(bgm_override).await.map_err(|e| {
+            anyhow::Error::msg(tr!("ファイルを開けませんでした。: {}", "Could not open the file: {}", e))
+        })?)
+    } else {
+        BgmSource::Level
+    };
+    let (events_tx, events_rx) = std::sync::mpsc::channel::<PipelineEvent>();
+    let events_label = logger.label.clone();
+    let events_thread = std::thread::spawn(move || {
+        let logger = Logger { label: events_label };
+        for event in events_rx {
+            let (stage, state) = match event {
+                PipelineEvent::Start(stage) => (stage, PhaseState::Start),
+                PipelineEvent::Finish(stage) => (stage, PhaseState::Finish),
+            };
+            let phase = match stage {
+                Stage::Bgm => Phase::Bgm,
+                Stage::Timing => Phase::Timing,
+                Stage::Effect => Phase::Effect,
+            };
+            logger.phase(phase, state);
+            match event {
+                PipelineEvent::Start(Stage::Bgm) => logger.info(&tr!("BGMを読み込んでいます...", "Loading the BGM...")),
+                PipelineEvent::Start(Stage::Timing) => {
+                    logger.info(&tr!("譜面を読み込んでいます...", "Loading the level..."))
+                }
+                PipelineEvent::Start(Stage::Effect) => {
+                    logger.info(&tr!("効果音を読み込んでいます...", "Loading sound effects..."))
+                }
+                PipelineEvent::Finish(_) => {}
+            }
+        }
+    });
+    let options = PipelineOptions {
+        bgm,
+        bgm_volume: args.bgm_volume,
+        shift: args.shift,
+        events: Some(events_tx),
+        effects: Some(effects.clone()),
+        range: args.range.clone(),
+    };
+    let prepared = pipeline::prepare(&level, &options).await;
+    drop(options);
+    tokio::task::spawn_blocking(move || events_thread.join().unwrap()).await?;
+    let Prepared {
+        bgm,
+        timing,
+        effect,
+        window,
+    } = prepared?;
+    if let Some(window) = &window {
+        logger.info(&tr!(
+            "{:.3}秒から{}までを生成します。",
+            "Rendering from {:.3}s to {}.",
+            window.start,
+            window.end.map_or(tr!("最後", "the end"), |end| tr!("{:.3}秒", "{:.3}s", end))
+        ));
+    }
+
+    let rx = pjsekai_soundgen_core::synthesis(&timing, &effect, args.notes_per_thread, args.unknown_clip).await?;
+    let warnings_label = logger.label.clone();
+    let (rx, threads) = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        let logger = Logger { label: warnings_label };
+        loop {
+            match rx.recv()? {
+                Progress::Warning { message, .. } => logger.warning(&message),
+                Progress::Info { threads } => return Ok((rx, threads)),
+                _ => unreachable!(),
+            }
+        }
+    })
+    .await??;
+    logger.phase(Phase::Synthesis, PhaseState::Start);
+    logger.info(&tr!("{}スレッドで合成を開始します。", "Starting synthesis on {} threads.", threads.len()));
+    events::emit(Event::SynthesisStart {
+        target: logger.target(),
+        threads: threads.iter().map(|(id, info)| ThreadEvent { id, max: info.max }).collect(),
+    });
+    let mut progresses_map: HashMap<String, ProgressBar> = HashMap::new();
+    for (name, info) in threads.iter() {
+        let progress = progress::PROGRESSES.add(
+            ProgressBar::new(info.max as u64)
+                .with_style(progress::bar_style(info.color.fg, info.color.bg))
+                .with_message(logger.format(name)),
+        );
+        progresses_map.insert(name.clone(), progress);
+    }
+    let target = logger.label.clone();
+    let clips: HashMap<String, String> = threads.into_iter().map(|(id, info)| (id, info.clip)).collect();
+    let clip_sounds = tokio::task::spawn_blocking(move || -> anyhow::Result<HashMap<String, Sound>> {
+        let mut clip_sounds: HashMap<String, Sound> = HashMap::new();
+        while !progresses_map.is_empty() {
+            match rx.recv()? {
+                Progress::Update { id, current } => {
+                    progresses_map.get(&id).unwrap().set_position(current as u64);
+                    events::emit(Event::Progress {
+                        target: target.as_deref(),
+                        thread: &id,
+                        current,
+                    });
+                }
+                Progress::Finish { id, sound } => {
+                    progresses_map.get(&id).unwrap().finish();
+                    events::emit(Event::ThreadFinish {
+                        target: target.as_deref(),
+                        thread: &id,
+                    });
+                    let clip_sound = clip_sounds.remove(&clips[&id]).unwrap_or_else(|| Sound::empty(None));
+                    clip_sounds.insert(clips[&id].clone(), clip_sound.overlay_at(&sound, 0.0));
+                    progresses_map.remove(&id);
+                }
+                _ => unreachable!(),
+            }
+        }
+        Ok(clip_sounds)
+    })
+    .await??;
+    logger.phase(Phase::Synthesis, PhaseState::Finish);
+    logger.info(&tr!("合成が完了しました。", "Synthesis finished."));
+
+    let grouping = args.stems.clone();
+    let (stems, final_bgm) = tokio::task::spawn_blocking(move || {
+        let (bgm, clip_sounds) = match &window {
+            Some(window) => (
+                bgm.map(|bgm| window.trim(bgm)),
+                clip_sounds.into_iter().map(|(clip, sound)| (clip, window.trim(sound))).collect(),
+            ),
+            None => (bgm, clip_sounds),
+        };
+        let stems = grouping.map(|grouping| grouping.split(clip_sounds.clone(), bgm.clone()));
+        let final_bgm = clip_sounds
+            .values()
+            .fold(bgm.unwrap_or_else(|| Sound::empty(None)), |merged, sound| merged.overlay_at(sound, 0.0));
+        (stems, final_bgm)
+    })
+    .await?;
+    let output = output_path(args, &name, batch);
+    logger.phase(Phase::Export, PhaseState::Start);
+    let mut export = args.export.clone();
+    if args.metadata {
+        let mut metadata = Metadata::from_level(&level);
+        if export.format.supports_cover() {
+            match level.fetch_cover().await {
+                Ok(Some(cover)) => metadata = metadata.with_cover(cover),
+                Ok(None) => {}
+                Err(err) => {
+                    logger.warning(&tr!("ジャケットを埋め込めませんでした：{}", "Could not embed the cover: {}", err))
+                }
+            }
+        }
+        export = export.with_metadata(metadata);
+    }
+    logger.info(&tr!("出力しています...", "Writing output..."));
+    let export_path = output.clone();
+    let stem_export = export.clone();
+    tokio::task::spawn_blocking(move || final_bgm.export(&export_path, &export)).await??;
+    events::emit(Event::Output {
+        target: logger.target(),
+        stem: None,
+        path: &output,
+    });
+    for (stem, sound) in stems.into_iter().flatten() {
+        let stem_path = stem_path(&output, &stem);
+        logger.info(&tr!("ステムを出力しています：{}", "Writing stem: {}", stem_path));
+        let export_path = stem_path.clone();
+        let mut export = stem_export.clone();
+        if let Some(metadata) = &mut export.metadata {
+            metadata.title = metadata.title.take().map(|title| format!("{} [{}]", title, stem));
+        }
+        tokio::task::spawn_blocking(move || sound.export(&export_path, &export)).await??;
+        events::emit(Event::Output {
+            target: logger.target(),
+            stem: Some(&stem),
+            path: &stem_path,
+        });
+    }
+    logger.phase(Phase::Export, PhaseState::Finish);
+    Ok(output)
+}
+
+pub async fn render_one(args: &Args, input: &str) {
+    match render(args, input, &EffectStore::default(), false).await {
+        Ok(output) => console::info(&tr!("完了しました：{}", "Done: {}", output)),
+        Err(err) => {
+            console::error(&err.to_string());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// 複数の譜面を並列に生成します。失敗した譜面があっても残りの譜面は生成し、最後に結果をまとめて表示します。
+pub async fn render_batch(args: &Args, inputs: Vec<String>) {
+    if let Some(output) = &args.output {
+        if let Err(err) = std::fs::create_dir_all(output) {
+            console::error(&tr!(
+                "出力先のフォルダを作成できませんでした：{}",
+                "Could not create the output folder: {}",
+                err
+            ));
+            std::process::exit(1);
+        }
+    }
+    console::info(&tr!(
+        "{}個の譜面を{}並列で生成します。",
+        "Rendering {} levels, {} at a time.",
+        inputs.len(),
+        args.jobs
+    ));
+    let effects = EffectStore::default();
+    let semaphore = Arc::new(Semaphore::new(args.jobs));
+    // 1つの譜面の合成や書き出しで他の譜面が止まらないよう、譜面毎に別のタスクで生成する
+    let args = Arc::new(args.clone());
+    let handles = inputs
+        .iter()
+        .map(|input| {
+            let args = args.clone();
+            let semaphore = semaphore.clone();
+            let effects = effects.clone();
+            let input = input.clone();
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire().await.unwrap();
+                let result = render(&args, &input, &effects, true).await;
+                if let Err(err) = &result {
+                    Logger { label: Some(input) }.error(&err.to_string());
+                }
+                result
+            })
+        })
+        .collect::<Vec<_>>();
+    let results = futures::future::join_all(handles)
+        .await
+        .into_iter()
+        .zip(inputs.iter())
+        .map(|(result, input)| {
+            // パニックした譜面も、失敗した譜面として結果に含める
+            result.unwrap_or_else(|err| {
+                let err = anyhow::Error::msg(tr!(
+                    "生成中に予期しないエラーが発生しました：{}",
+                    "Rendering failed unexpectedly: {}",
+                    err
+                ));
+                Logger {
+                    label: Some(input.clone()),
+                }
+                .error(&err.to_string());
+                Err(err)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let failures = results.iter().filter(|result| result.is_err()).count();
+    events::emit(Event::Summary {
+        succeeded: results.len() - failures,
+        failed: failures,
+        results: inputs
+            .iter()
+            .zip(results.iter())
+            .map(|(input, result)| ResultEvent {
+                target: input,
+                output: result.as_ref().ok().map(|output| output.as_str()),
+                error: result.as_ref().err().map(|err| err.to_string()),
+            })
+            .collect(),
+    });
+    console::info(&tr!(
+        "完了しました：成功 {}、失敗 {}",
+        "Done: {} succeeded, {} failed",
+        results.len() - failures,
+        failures
+    ));
+    if !events::enabled() {
+        for (input, result) in inputs.iter().zip(results.iter()) {
+            match result {
+                Ok(output) => println!("  {}\u{2713}{} {} -> {}", rgb!(0x88cb7f), rgb!(), input, output),
+                Err(err) => println!(
+                    "{}",
+                    tr!("  {}\u{2717}{} {}：{}", "  {}\u{2717}{} {}: {}", rgb!(0xff5a91), rgb!(), input, err)
+                ),
+            }
+        }
+    }
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
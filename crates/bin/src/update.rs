@@ -0,0 +1,93 @@
+use crate::console;
+use dirs::{data_local_dir, state_dir};
+use octocrab::Octocrab;
+use pjsekai_soundgen_core::tr;
+use std::{path::PathBuf, time::Duration};
+use tokio::task::JoinHandle;
+
+/// 空でない値（`0`以外）が設定されている場合、更新の確認を行いません。
+pub static DISABLE_ENV: &str = "PJSEKAI_SOUNDGEN_NO_UPDATE_CHECK";
+
+/// 更新を確認する間隔。
+const INTERVAL: chrono::Duration = chrono::Duration::days(1);
+/// GitHubの応答を待つ最大の時間。
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+pub fn disabled_by_env() -> bool {
+    std::env::var(DISABLE_ENV).is_ok_and(|value| !value.is_empty() && value != "0")
+}
+
+/// 最後に確認した日時を保存するファイル。
+fn stamp_path() -> PathBuf {
+    let mut path = state_dir().or_else(data_local_dir).unwrap_or_else(|| PathBuf::from("./state"));
+    path.push("pjsekai-soundgen-rust");
+    path.push("update-check");
+    path
+}
+
+fn should_check() -> bool {
+    let Ok(stamp) = std::fs::read_to_string(stamp_path()) else {
+        return true;
+    };
+    match chrono::DateTime::parse_from_rfc3339(stamp.trim()) {
+        Ok(last_checked) => chrono::Local::now().signed_duration_since(last_checked) >= INTERVAL,
+        Err(_) => true,
+    }
+}
+
+/// 確認した日時を保存します。保存できなくても次回また確認するだけなので、エラーは無視します。
+fn write_stamp() {
+    let path = stamp_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let _ = std::fs::write(path, chrono::Local::now().to_rfc3339());
+}
+
+pub struct Release {
+    pub version: String,
+    pub url: String,
+}
+
+async fn fetch_latest() -> anyhow::Result<Release> {
+    let octocrab = Octocrab::builder().build()?;
+    let release = octocrab.repos("sevenc-nanashi", "pjsekai-soundgen-rust").releases().get_latest().await?;
+    Ok(Release {
+        version: release.tag_name.trim_start_matches('v').to_string(),
+        url: release.html_url.to_string(),
+    })
+}
+
+/// バックグラウンドで更新を確認します。前回の確認から1日経っていない場合は`None`を返します。
+pub fn spawn() -> Option<JoinHandle<Option<Release>>> {
+    if !should_check() {
+        return None;
+    }
+    write_stamp();
+    Some(tokio::spawn(async {
+        match tokio::time::timeout(TIMEOUT, fetch_latest()).await {
+            Ok(Ok(release)) => Some(release),
+            Ok(Err(_)) | Err(_) => None,
+        }
+    }))
+}
+
+/// 確認の結果を待ち、新しいバージョンがあれば表示します。確認に失敗した場合は何も表示しません。
+pub async fn finish(handle: Option<JoinHandle<Option<Release>>>) {
+    let Some(handle) = handle else {
+        return;
+    };
+    let Ok(Some(release)) = handle.await else {
+        return;
+    };
+    let current_version = env!("CARGO_PKG_VERSION");
+    if release.version != current_version {
+        console::info(&tr!(
+            "新しいバージョンがリリースされています：v{} -> v{}",
+            "A new version is available: v{} -> v{}",
+            current_version,
+            release.version
+        ));
+        console::info(&tr!("ダウンロード：{}", "Download: {}", release.url));
+    }
+}
@@ -11,3 +11,33 @@ macro_rules! rgb {
 }
 
 pub(crate) use rgb;
+
+/// `500M`や`1.5G`のようなサイズ表記をバイト数に変換します。
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim().to_ascii_uppercase();
+    let size = size.trim_end_matches('B').trim_end_matches('I');
+    let (number, unit) = match size.char_indices().last()? {
+        (index, 'K') => (&size[..index], 1u64 << 10),
+        (index, 'M') => (&size[..index], 1 << 20),
+        (index, 'G') => (&size[..index], 1 << 30),
+        (index, 'T') => (&size[..index], 1 << 40),
+        _ => (size, 1),
+    };
+    let number = number.trim().parse::<f64>().ok().filter(|n| *n >= 0.0)?;
+    Some((number * unit as f64) as u64)
+}
+
+pub fn format_size(size: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", size, units[0])
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
@@ -0,0 +1,143 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// ユーザーの設定ファイルやネットワークに触れないように、一時ディレクトリでCLIを実行します。
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pjsekai-soundgen"))
+        .args(args)
+        .args(["--lang", "en", "--no-update-check"])
+        .current_dir(dir)
+        .env("HOME", dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("PJSEKAI_SOUNDGEN_NO_UPDATE_CHECK", "1")
+        .output()
+        .unwrap()
+}
+
+fn text(output: &Output) -> String {
+    format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr))
+}
+
+/// 不正な値で失敗し、パニックせずにエラーを表示したことを確認します。
+fn assert_rejected(args: &[&str], expected: &str) {
+    let dir = tempfile::tempdir().unwrap();
+    let output = run(dir.path(), args);
+    let text = text(&output);
+    assert_eq!(output.status.code(), Some(1), "{:?}: {}", args, text);
+    assert!(!text.contains("panicked"), "{:?}: {}", args, text);
+    assert!(text.contains(expected), "{:?}: {}", args, text);
+}
+
+#[test]
+fn rejects_invalid_export_options() {
+    assert_rejected(&["--bitrate", "abc", "x"], "Invalid value for --bitrate: abc");
+    assert_rejected(&["--quality", "high", "x"], "Invalid value for --quality: high");
+    assert_rejected(&["--sample-rate", "-1", "x"], "Invalid value for --sample-rate: -1");
+    assert_rejected(&["--bit-depth", "24bit", "x"], "Invalid value for --bit-depth: 24bit");
+}
+
+#[test]
+fn rejects_invalid_http_options() {
+    assert_rejected(&["--retries", "-1", "x"], "Invalid value for --retries: -1");
+    assert_rejected(&["--read-timeout", "-5", "x"], "Invalid value for --read-timeout: -5");
+    assert_rejected(&["--connect-timeout", "0", "x"], "Invalid value for --connect-timeout: 0");
+    assert_rejected(&["--connect-timeout", "inf", "x"], "Invalid value for --connect-timeout: inf");
+}
+
+#[test]
+fn rejects_invalid_jobs() {
+    assert_rejected(&["--jobs", "two", "x"], "Invalid value for --jobs: two");
+    assert_rejected(&["--jobs", "0", "x"], "Invalid value for --jobs: 0");
+}
+
+#[test]
+fn rejects_zero_notes_per_thread() {
+    assert_rejected(&["--notes-per-thread", "0", "x"], "Invalid value for --notes-per-thread: 0");
+}
+
+#[test]
+fn rejects_invalid_page() {
+    assert_rejected(&["search", "--page", "0", "x"], "Invalid value for --page: 0");
+    assert_rejected(&["search", "--page", "-2", "x"], "Invalid value for --page: -2");
+    assert_rejected(&["search", "--page", "next", "x"], "Invalid value for --page: next");
+}
+
+#[test]
+fn fails_search_when_every_server_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("config").join("pjsekai-soundgen-rust");
+    std::fs::create_dir_all(&config).unwrap();
+    let servers = ["a", "b"].map(|prefix| {
+        format!(
+            "[[servers]]\nprefix = \"{0}\"\nid = \"{0}\"\nname = \"{0}\"\nurl = \"http://127.0.0.1:9\"\n",
+            prefix
+        )
+    });
+    std::fs::write(config.join("servers.toml"), servers.concat()).unwrap();
+
+    let output = run(dir.path(), &["search", "--retries", "0", "x"]);
+    let text = text(&output);
+    assert_eq!(output.status.code(), Some(1), "{}", text);
+    assert_eq!(text.matches("Failed to search for levels").count(), 2, "{}", text);
+}
+
+#[test]
+fn prints_only_json_with_json_flag() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache_dir = dir.path().join("cache");
+    for args in [
+        vec!["config", "--json"],
+        vec!["cache", "list", "--json", "--cache-dir", cache_dir.to_str().unwrap()],
+        vec!["cache", "info", "--json", "--cache-dir", cache_dir.to_str().unwrap()],
+    ] {
+        let output = run(dir.path(), &args);
+        assert!(output.status.success(), "{:?}: {}", args, text(&output));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(!stdout.is_empty(), "{:?}", args);
+        for line in stdout.lines() {
+            let event: serde_json::Value =
+                serde_json::from_str(line).unwrap_or_else(|_| panic!("{:?}: {}", args, line));
+            assert_eq!(event["type"], "report", "{:?}: {}", args, line);
+        }
+    }
+}
+
+#[test]
+fn renders_each_level_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = run(dir.path(), &["--json", "--offline", "xxxx-a", "#xxxx-a", "xxxx-b", "xxxx-a"]);
+    assert_eq!(output.status.code(), Some(1), "{}", text(&output));
+    let summary = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .find(|event| event["type"] == "summary")
+        .unwrap();
+    let targets = summary["results"].as_array().unwrap().iter().map(|r| r["target"].clone()).collect::<Vec<_>>();
+    assert_eq!(targets, ["xxxx-a", "xxxx-b"]);
+    assert_eq!(summary["failed"], 2);
+}
+
+#[test]
+fn reads_stems_mode_in_both_spellings() {
+    let dir = tempfile::tempdir().unwrap();
+    for (args, expected) in [
+        (vec!["config", "--json", "--stems", "critical"], "critical"),
+        (vec!["config", "--json", "--stems=critical"], "critical"),
+        (vec!["config", "--json", "--stems", "groups.toml"], "groups.toml"),
+        (vec!["config", "--json", "--stems=groups.toml"], "groups.toml"),
+    ] {
+        let output = run(dir.path(), &args);
+        assert!(output.status.success(), "{:?}: {}", args, text(&output));
+        let event: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let stems = event["data"]["settings"].as_array().unwrap().iter().find(|s| s["name"] == "stems").unwrap();
+        assert_eq!(stems["value"], expected, "{:?}", args);
+        assert_eq!(stems["source"], "command line", "{:?}", args);
+    }
+
+    // モードが譜面IDとして扱われると、2つの譜面の一括生成になる
+    let output = run(dir.path(), &["--json", "--offline", "--stems", "critical", "xxxx-a"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("\"summary\""), "{}", stdout);
+    assert!(stdout.contains("xxxx-a") && !stdout.contains("critical"), "{}", stdout);
+}
@@ -0,0 +1,368 @@
+use dirs::cache_dir;
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::Result;
+use crate::i18n;
+use crate::utils::debug;
+
+pub static CACHE_DIR_ENV: &str = "PJSEKAI_SOUNDGEN_CACHE_DIR";
+static META_SUFFIX: &str = ".meta.json";
+static LOCK_SUFFIX: &str = ".lock";
+static TEMP_SUFFIX: &str = ".tmp";
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+/// そのままファイル名に使うキーの最大の長さ。
+const MAX_PLAIN_KEY_LEN: usize = 128;
+/// Windowsでファイル名に使えない名前。
+static RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheKind {
+    LevelInfo,
+    LevelData,
+    Bgm,
+    Cover,
+    EffectData,
+    EffectAudio,
+    EffectPcm,
+    /// メタデータの無い古いキャッシュ。
+    Unknown,
+}
+
+impl std::fmt::Display for CacheKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            CacheKind::LevelInfo => "level_info",
+            CacheKind::LevelData => "level_data",
+            CacheKind::Bgm => "bgm",
+            CacheKind::Cover => "cover",
+            CacheKind::EffectData => "effect_data",
+            CacheKind::EffectAudio => "effect_audio",
+            CacheKind::EffectPcm => "effect_pcm",
+            CacheKind::Unknown => "unknown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl CacheKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CacheKind::LevelInfo => i18n::pick("譜面情報", "level info"),
+            CacheKind::LevelData => i18n::pick("譜面データ", "level data"),
+            CacheKind::Bgm => "BGM",
+            CacheKind::Cover => i18n::pick("ジャケット", "cover"),
+            CacheKind::EffectData => i18n::pick("効果音データ", "effect data"),
+            CacheKind::EffectAudio => i18n::pick("効果音", "sound effects"),
+            CacheKind::EffectPcm => i18n::pick("デコード済みの効果音", "decoded sound effects"),
+            CacheKind::Unknown => i18n::pick("不明なデータ", "unknown data"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub key: String,
+    pub server: String,
+    pub kind: CacheKind,
+    pub size: u64,
+    /// 最後に使われた時刻（UNIX時間、秒）。
+    pub last_access: u64,
+}
+
+/// ダウンロードしたデータのキャッシュ。
+/// データは`<dir>/<key>`に、メタデータは`<dir>/<key>.meta.json`に保存されます。
+/// ファイル名に使えないキーは、ハッシュを付けた名前に置き換えられます。
+#[derive(Debug, Clone)]
+pub struct Cache {
+    pub dir: PathBuf,
+    /// キャッシュの最大サイズ（バイト）。超えた場合は使われていない順に削除されます。
+    pub max_size: Option<u64>,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(Self::default_dir())
+    }
+}
+
+/// キャッシュのキー毎のロック。ドロップ時に解放されます。
+#[derive(Debug)]
+pub struct CacheLock {
+    file: std::fs::File,
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn is_plain_key(key: &str) -> bool {
+    let stem = key.split('.').next().unwrap_or_default();
+    !key.is_empty()
+        && key.len() <= MAX_PLAIN_KEY_LEN
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        && !key.starts_with('.')
+        && !key.ends_with('.')
+        && ![META_SUFFIX, LOCK_SUFFIX, TEMP_SUFFIX].iter().any(|suffix| key.ends_with(suffix))
+        && !RESERVED_NAMES.iter().any(|name| stem.eq_ignore_ascii_case(name))
+}
+
+/// キーをキャッシュフォルダ内のファイル名にします。
+/// `[A-Za-z0-9._-]`以外の文字を含むキーや予約された名前は、フォルダの外や他のキャッシュのファイルを指さないよう、
+/// 使えない文字を`_`にしてキーのハッシュを付けた名前にします。
+fn file_name(key: &str) -> String {
+    if is_plain_key(key) {
+        return key.to_string();
+    }
+    let readable = key
+        .chars()
+        .take(64)
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("{}+{:x}", readable, Sha1::digest(key.as_bytes()))
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_size: None,
+        }
+    }
+
+    /// `PJSEKAI_SOUNDGEN_CACHE_DIR`が設定されていればそれを、無ければユーザーのキャッシュフォルダを返します。
+    pub fn default_dir() -> PathBuf {
+        if let Some(dir) = std::env::var_os(CACHE_DIR_ENV).filter(|dir| !dir.is_empty()) {
+            return PathBuf::from(dir);
+        }
+        let mut path = cache_dir().unwrap_or_else(|| PathBuf::from("./cache"));
+        path.push("pjsekai-soundgen-rust");
+        path
+    }
+
+    pub fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(file_name(key))
+    }
+
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}{}", file_name(key), META_SUFFIX))
+    }
+
+    fn lock_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}{}", file_name(key), LOCK_SUFFIX))
+    }
+
+    /// キーをロックします。別のプロセスが同じキーをロックしている場合は、解放されるまで待ちます。
+    pub async fn lock(&self, key: &str) -> Result<CacheLock> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.lock_path(key);
+        let file = tokio::task::spawn_blocking(move || -> std::io::Result<std::fs::File> {
+            let file = std::fs::OpenOptions::new().create(true).truncate(false).write(true).open(path)?;
+            file.lock_exclusive()?;
+            Ok(file)
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+        Ok(CacheLock { file })
+    }
+
+    /// ロックファイルを削除します。他のプロセスがロックしている場合は残します。
+    /// 削除の直前に別のプロセスがロックしても、書き込みは置き換えで行われるため、同じデータを取得し直すだけで済みます。
+    async fn remove_lock(&self, path: PathBuf) -> Result<()> {
+        tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            let file = match std::fs::OpenOptions::new().write(true).open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            if file.try_lock_exclusive().is_err() {
+                return Ok(());
+            }
+            drop(file);
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+        Ok(())
+    }
+
+    /// 一時ファイルに書き込んでから置き換えることで、書き込み途中のファイルが読まれないようにします。
+    async fn write_atomic(&self, path: PathBuf, bytes: &[u8]) -> Result<()> {
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        let count = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        temp_name.push(format!(".{}-{}{}", std::process::id(), count, TEMP_SUFFIX));
+        let temp_path = path.with_file_name(temp_name);
+        if let Err(e) = tokio::fs::write(&temp_path, bytes).await {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(e.into());
+        }
+        if let Err(e) = tokio::fs::rename(&temp_path, &path).await {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// キャッシュを読み込み、最終使用時刻を更新します。
+    pub async fn read(&self, key: &str) -> Option<Vec<u8>> {
+        let bytes = tokio::fs::read(self.path(key)).await.ok()?;
+        if let Some(mut entry) = self.entry(key).await {
+            entry.last_access = now();
+            if let Err(e) = self.write_meta(&entry).await {
+                debug!(format!("failed to update cache metadata: {}", e));
+            }
+        }
+        Some(bytes)
+    }
+
+    pub async fn write(&self, key: &str, server: &str, kind: CacheKind, bytes: &[u8]) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        self.write_atomic(self.path(key), bytes).await?;
+        self.write_meta(&CacheEntry {
+            key: key.to_string(),
+            server: server.to_string(),
+            kind,
+            size: bytes.len() as u64,
+            last_access: now(),
+        })
+        .await?;
+
+        if let Some(max_size) = self.max_size {
+            self.prune(max_size, Some(key)).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_meta(&self, entry: &CacheEntry) -> Result<()> {
+        self.write_atomic(self.meta_path(&entry.key), &serde_json::to_vec(entry)?).await
+    }
+
+    pub async fn remove(&self, key: &str) -> Result<()> {
+        for path in [self.path(key), self.meta_path(key)] {
+            match tokio::fs::remove_file(path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn entry(&self, key: &str) -> Option<CacheEntry> {
+        self.read_entry(&file_name(key), key).await
+    }
+
+    /// ファイル名が`name`のキャッシュを読み込みます。メタデータが無い場合は`key`のキャッシュとして扱います。
+    async fn read_entry(&self, name: &str, key: &str) -> Option<CacheEntry> {
+        let metadata = tokio::fs::metadata(self.dir.join(name)).await.ok()?;
+        if let Some(entry) = tokio::fs::read(self.dir.join(format!("{}{}", name, META_SUFFIX)))
+            .await
+            .ok()
+            .and_then(|meta| serde_json::from_slice::<CacheEntry>(&meta).ok())
+        {
+            return Some(entry);
+        }
+        Some(CacheEntry {
+            key: key.to_string(),
+            server: String::new(),
+            kind: CacheKind::Unknown,
+            size: metadata.len(),
+            last_access: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        })
+    }
+
+    /// キャッシュの一覧を、最近使われた順に返します。
+    pub async fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let mut entries = vec![];
+        let mut read_dir = match tokio::fs::read_dir(&self.dir).await {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(file) = read_dir.next_entry().await? {
+            let name = file.file_name().to_string_lossy().to_string();
+            if [META_SUFFIX, LOCK_SUFFIX, TEMP_SUFFIX].iter().any(|suffix| name.ends_with(suffix))
+                || !file.file_type().await?.is_file()
+            {
+                continue;
+            }
+            if let Some(entry) = self.read_entry(&name, &name).await {
+                entries.push(entry);
+            }
+        }
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.last_access));
+        Ok(entries)
+    }
+
+    pub async fn total_size(&self) -> Result<u64> {
+        Ok(self.entries().await?.iter().map(|entry| entry.size).sum())
+    }
+
+    /// 合計サイズが`max_size`以下になるまで、使われていない順に削除します。
+    /// 削除したキャッシュを返します。
+    pub async fn prune(&self, max_size: u64, keep: Option<&str>) -> Result<Vec<CacheEntry>> {
+        let entries = self.entries().await?;
+        let mut total: u64 = entries.iter().map(|entry| entry.size).sum();
+        let mut removed = vec![];
+        for entry in entries.into_iter().rev() {
+            if total <= max_size {
+                break;
+            }
+            if keep == Some(entry.key.as_str()) {
+                continue;
+            }
+            self.remove(&entry.key).await?;
+            self.remove_lock(self.lock_path(&entry.key)).await?;
+            total -= entry.size;
+            removed.push(entry);
+        }
+        Ok(removed)
+    }
+
+    /// 全てのキャッシュと使われていないロックファイルを削除し、削除したキャッシュの数を返します。
+    pub async fn clear(&self) -> Result<usize> {
+        let entries = self.entries().await?;
+        for entry in entries.iter() {
+            self.remove(&entry.key).await?;
+        }
+        let mut read_dir = match tokio::fs::read_dir(&self.dir).await {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entries.len()),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(file) = read_dir.next_entry().await? {
+            let name = file.file_name().to_string_lossy().to_string();
+            if name.ends_with(LOCK_SUFFIX) {
+                self.remove_lock(file.path()).await?;
+            }
+        }
+        Ok(entries.len())
+    }
+}
@@ -0,0 +1,162 @@
+use crate::cache::CacheKind;
+use crate::tr;
+
+use std::fmt;
+use std::path::PathBuf;
+use thiserror::Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// `pjsekai_soundgen_core`のエラー。`Display`で表示用のメッセージを返します。
+#[derive(Debug, Error)]
+pub enum Error {
+    Offline {
+        url: String,
+    },
+    NotCached {
+        kind: CacheKind,
+        key: String,
+    },
+    Network {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    Timeout {
+        url: String,
+    },
+    Status {
+        status: u16,
+        url: String,
+    },
+    HashMismatch {
+        url: String,
+    },
+    /// gzipやzipの展開に失敗しました。
+    Decompress(#[source] std::io::Error),
+    Json(#[from] serde_json::Error),
+    /// 譜面のエンティティに必要な値がありません。`entity`はエンティティの名前です。
+    CorruptLevel {
+        archetype: String,
+        entity: Option<String>,
+        field: String,
+    },
+    /// 譜面全体の構造が不正です。
+    InvalidLevel(String),
+    UnknownClip {
+        clip: String,
+    },
+    AudioDecode(String),
+    AudioEncode(String),
+    Config {
+        path: PathBuf,
+        message: String,
+    },
+    UnknownServer {
+        level: String,
+    },
+    /// 引数や設定の値が不正です。
+    InvalidInput(String),
+    Io(#[from] std::io::Error),
+    /// 何をしようとして失敗したかを付け加えたエラー。
+    Context {
+        context: String,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Error::Offline { url } => {
+                tr!("オフラインモードのため通信できません：{}", "Cannot connect in offline mode: {}", url)
+            }
+            Error::NotCached { kind, key } => tr!(
+                "オフラインモードですが、{}がキャッシュにありません：{}",
+                "Offline mode is enabled, but the {} is not cached: {}",
+                kind.label(),
+                key
+            ),
+            Error::Network { url, source } => {
+                tr!("通信に失敗しました（{}）：{}", "Request failed ({}): {}", url, source)
+            }
+            Error::Timeout { url } => tr!("タイムアウトしました：{}", "Timed out: {}", url),
+            Error::Status { status, url } => tr!("HTTP {}：{}", "HTTP {}: {}", status, url),
+            Error::HashMismatch { url } => tr!(
+                "データが壊れています（ハッシュが一致しません）：{}",
+                "Downloaded data is corrupt (hash mismatch): {}",
+                url
+            ),
+            Error::Decompress(source) => tr!("展開に失敗しました：{}", "Failed to decompress: {}", source),
+            Error::Json(source) => tr!("JSONが不正です：{}", "Invalid JSON: {}", source),
+            Error::CorruptLevel {
+                archetype,
+                entity: Some(entity),
+                field,
+            } => tr!(
+                "譜面データが壊れています：{}（{}）に{}がありません",
+                "Level data is corrupt: {} ({}) has no {}",
+                archetype,
+                entity,
+                field
+            ),
+            Error::CorruptLevel {
+                archetype,
+                entity: None,
+                field,
+            } => tr!(
+                "譜面データが壊れています：{}に{}がありません",
+                "Level data is corrupt: {} has no {}",
+                archetype,
+                field
+            ),
+            Error::InvalidLevel(reason) => tr!("譜面データが壊れています：{}", "Level data is corrupt: {}", reason),
+            Error::UnknownClip { clip } => tr!("不明なSEです：{}", "Unknown sound effect: {}", clip),
+            Error::AudioDecode(message) => {
+                tr!("音声の読み込みに失敗しました：{}", "Failed to decode audio: {}", message)
+            }
+            Error::AudioEncode(message) => {
+                tr!("音声の書き出しに失敗しました：{}", "Failed to encode audio: {}", message)
+            }
+            Error::Config { path, message } => {
+                tr!("設定ファイルが不正です（{}）：{}", "Invalid config file ({}): {}", path.display(), message)
+            }
+            Error::UnknownServer { level } => {
+                tr!("サーバーを特定できませんでした：{}", "Could not determine the server for {}", level)
+            }
+            Error::InvalidInput(message) => message.clone(),
+            Error::Io(source) => source.to_string(),
+            Error::Context { context, source } => format!("{}: {}", context, source),
+        };
+        f.write_str(&message)
+    }
+}
+
+impl Error {
+    /// `Context`を取り除いた、元のエラーを返します。
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } => source.root(),
+            error => error,
+        }
+    }
+
+    pub(crate) fn invalid_input(message: impl Into<String>) -> Self {
+        Error::InvalidInput(message.into())
+    }
+}
+
+pub(crate) trait Context<T> {
+    /// エラーに`context`を付け加えます。
+    fn context(self, context: &str) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for std::result::Result<T, E> {
+    fn context(self, context: &str) -> Result<T> {
+        self.map_err(|e| Error::Context {
+            context: context.to_string(),
+            source: Box::new(e.into()),
+        })
+    }
+}
@@ -0,0 +1,398 @@
+use crate::error::{Error, Result};
+use crate::level::Level;
+use crate::tr;
+
+use std::path::Path;
+use std::str::FromStr;
+
+/// 出力形式。コンテナとコーデックの組を表します。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Mp3,
+    /// AAC（m4a）。
+    Aac,
+    /// Vorbis（ogg）。
+    Vorbis,
+    /// Opus（opus）。
+    Opus,
+    Flac,
+    Wav,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Mp3 => "mp3",
+            Format::Aac => "m4a",
+            Format::Vorbis => "ogg",
+            Format::Opus => "opus",
+            Format::Flac => "flac",
+            Format::Wav => "wav",
+        }
+    }
+
+    /// 拡張子から出力形式を推測します。
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "mp3" => Some(Format::Mp3),
+            "m4a" | "aac" => Some(Format::Aac),
+            "ogg" | "oga" => Some(Format::Vorbis),
+            "opus" => Some(Format::Opus),
+            "flac" => Some(Format::Flac),
+            "wav" => Some(Format::Wav),
+            _ => None,
+        }
+    }
+
+    fn muxer(&self) -> &'static str {
+        match self {
+            Format::Mp3 => "mp3",
+            Format::Aac => "ipod",
+            Format::Vorbis | Format::Opus => "ogg",
+            Format::Flac => "flac",
+            Format::Wav => "wav",
+        }
+    }
+
+    pub fn is_lossless(&self) -> bool {
+        matches!(self, Format::Flac | Format::Wav)
+    }
+
+    /// ジャケット画像を埋め込めるかどうか。
+    pub fn supports_cover(&self) -> bool {
+        matches!(self, Format::Mp3 | Format::Aac | Format::Flac)
+    }
+
+    fn default_bitrate(&self) -> Option<u32> {
+        match self {
+            Format::Mp3 => Some(320),
+            Format::Aac => Some(256),
+            Format::Vorbis => Some(256),
+            Format::Opus => Some(192),
+            Format::Flac | Format::Wav => None,
+        }
+    }
+
+    /// 指定できるビットレートの範囲（kbps）。
+    fn bitrate_range(&self) -> Option<(u32, u32)> {
+        match self {
+            Format::Mp3 => Some((8, 320)),
+            Format::Aac => Some((8, 512)),
+            Format::Vorbis => Some((32, 500)),
+            Format::Opus => Some((6, 510)),
+            Format::Flac | Format::Wav => None,
+        }
+    }
+
+    /// 指定できる品質（`-q:a`）の範囲。
+    fn quality_range(&self) -> Option<(f32, f32)> {
+        match self {
+            Format::Mp3 => Some((0.0, 9.0)),
+            Format::Aac => Some((0.1, 2.0)),
+            Format::Vorbis => Some((-1.0, 10.0)),
+            Format::Opus | Format::Flac | Format::Wav => None,
+        }
+    }
+
+    fn sample_rates(&self) -> &'static [u32] {
+        match self {
+            Format::Mp3 => &[8000, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000],
+            Format::Opus => &[8000, 12000, 16000, 24000, 48000],
+            Format::Aac | Format::Vorbis | Format::Flac | Format::Wav => {
+                &[8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000]
+            }
+        }
+    }
+
+    fn bit_depths(&self) -> &'static [u32] {
+        match self {
+            Format::Flac => &[16, 24],
+            Format::Wav => &[16, 24, 32],
+            _ => &[],
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Format::Mp3 => "mp3",
+            Format::Aac => "aac",
+            Format::Vorbis => "vorbis",
+            Format::Opus => "opus",
+            Format::Flac => "flac",
+            Format::Wav => "wav",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "mp3" => Ok(Format::Mp3),
+            "aac" | "m4a" => Ok(Format::Aac),
+            "vorbis" | "ogg" => Ok(Format::Vorbis),
+            "opus" => Ok(Format::Opus),
+            "flac" => Ok(Format::Flac),
+            "wav" => Ok(Format::Wav),
+            _ => Err(Error::invalid_input(tr!(
+                "出力形式が不正です：{}（mp3、aac、vorbis、opus、flac、wavのいずれかを指定してください）",
+                "Invalid output format: {} (expected mp3, aac, vorbis, opus, flac or wav)",
+                s
+            ))),
+        }
+    }
+}
+
+/// 出力ファイルに書き込むタグ。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub comment: Option<String>,
+    /// 埋め込むジャケット画像（PNGまたはJPEG）。
+    pub cover: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// 譜面情報からタグを作ります。タイトルにはレベルを、コメントには譜面作者と譜面のURLを含めます。
+    pub fn from_level(level: &Level) -> Self {
+        let info = &level.info;
+        Self {
+            title: Some(format!("{} (Lv. {})", info.title, info.rating)),
+            artist: Some(info.artists.clone()),
+            album: Some(info.title.clone()),
+            comment: Some(tr!("譜面作者：{} / {}", "Charted by {} / {}", info.author, level.url())),
+            cover: None,
+        }
+    }
+
+    pub fn with_cover(mut self, cover: Vec<u8>) -> Self {
+        self.cover = Some(cover);
+        self
+    }
+
+    fn tags(&self) -> Vec<(&'static str, &str)> {
+        [("title", &self.title), ("artist", &self.artist), ("album", &self.album), ("comment", &self.comment)]
+            .into_iter()
+            .filter_map(|(key, value)| value.as_deref().map(|value| (key, value)))
+            .collect()
+    }
+}
+
+/// 出力の設定。`validate`で、ffmpegを起動する前に組み合わせを確認できます。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportSettings {
+    pub format: Format,
+    /// ビットレート（kbps）。`None`の場合は形式毎の既定値を使います。
+    pub bitrate: Option<u32>,
+    /// 可変ビットレートの品質（ffmpegの`-q:a`）。`bitrate`とは同時に指定できません。
+    pub quality: Option<f32>,
+    /// サンプルレート（Hz）。`None`の場合は48000Hzで出力します。
+    pub sample_rate: Option<u32>,
+    /// ビット深度。可逆圧縮・無圧縮の形式でのみ指定できます。
+    pub bit_depth: Option<u32>,
+    /// 書き込むタグ。ジャケット画像は対応している形式でのみ埋め込まれます。
+    pub metadata: Option<Metadata>,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self::new(Format::Mp3)
+    }
+}
+
+impl ExportSettings {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            bitrate: None,
+            quality: None,
+            sample_rate: None,
+            bit_depth: None,
+            metadata: None,
+        }
+    }
+
+    /// 出力先の拡張子から形式を決めます。分からない場合はmp3になります。
+    pub fn for_path(path: &str) -> Self {
+        Self::new(Format::from_path(path).unwrap_or(Format::Mp3))
+    }
+
+    pub fn with_bitrate(mut self, bitrate: u32) -> Self {
+        self.bitrate = Some(bitrate);
+        self
+    }
+
+    pub fn with_quality(mut self, quality: f32) -> Self {
+        self.quality = Some(quality);
+        self
+    }
+
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    pub fn with_bit_depth(mut self, bit_depth: u32) -> Self {
+        self.bit_depth = Some(bit_depth);
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// 埋め込むジャケット画像。形式が対応していない場合は`None`を返します。
+    pub(crate) fn cover(&self) -> Option<&[u8]> {
+        self.metadata.as_ref()?.cover.as_deref().filter(|_| self.format.supports_cover())
+    }
+
+    /// 設定の組み合わせが正しいかを確認します。
+    pub fn validate(&self) -> Result<()> {
+        let format = self.format;
+        if self.bitrate.is_some() && self.quality.is_some() {
+            return Err(Error::invalid_input(tr!(
+                "ビットレートと品質は同時に指定できません。",
+                "Bitrate and quality cannot be specified together."
+            )));
+        }
+        if let Some(bitrate) = self.bitrate {
+            let Some((min, max)) = format.bitrate_range() else {
+                return Err(Error::invalid_input(tr!(
+                    "{}ではビットレートを指定できません。",
+                    "{} does not support a bitrate.",
+                    format
+                )));
+            };
+            if !(min..=max).contains(&bitrate) {
+                return Err(Error::invalid_input(tr!(
+                    "{}のビットレートは{}k〜{}kで指定してください：{}k",
+                    "The bitrate for {} must be between {}k and {}k: {}k",
+                    format,
+                    min,
+                    max,
+                    bitrate
+                )));
+            }
+        }
+        if let Some(quality) = self.quality {
+            let Some((min, max)) = format.quality_range() else {
+                return Err(Error::invalid_input(tr!(
+                    "{}では品質を指定できません。",
+                    "{} does not support a quality setting.",
+                    format
+                )));
+            };
+            if !(min..=max).contains(&quality) {
+                return Err(Error::invalid_input(tr!(
+                    "{}の品質は{}〜{}で指定してください：{}",
+                    "The quality for {} must be between {} and {}: {}",
+                    format,
+                    min,
+                    max,
+                    quality
+                )));
+            }
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            if !format.sample_rates().contains(&sample_rate) {
+                return Err(Error::invalid_input(tr!(
+                    "{}では{}Hzで出力できません。（{}）",
+                    "{} cannot be written at {} Hz. ({})",
+                    format,
+                    sample_rate,
+                    format.sample_rates().iter().map(|rate| rate.to_string()).collect::<Vec<_>>().join(", ")
+                )));
+            }
+        }
+        if let Some(bit_depth) = self.bit_depth {
+            if format.bit_depths().is_empty() {
+                return Err(Error::invalid_input(tr!(
+                    "{}ではビット深度を指定できません。",
+                    "{} does not support a bit depth.",
+                    format
+                )));
+            }
+            if !format.bit_depths().contains(&bit_depth) {
+                return Err(Error::invalid_input(tr!(
+                    "{}のビット深度は{}のいずれかで指定してください：{}",
+                    "The bit depth for {} must be one of {}: {}",
+                    format,
+                    format.bit_depths().iter().map(|depth| depth.to_string()).collect::<Vec<_>>().join(", "),
+                    bit_depth
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// 出力先の拡張子が形式と矛盾していないかを確認します。
+    pub fn validate_path(&self, path: &str) -> Result<()> {
+        match Format::from_path(path) {
+            Some(format) if format != self.format => Err(Error::invalid_input(tr!(
+                "出力先の拡張子が出力形式（{}）と一致しません：{}（.{}を指定してください）",
+                "The output extension does not match the format ({}): {} (use .{})",
+                self.format,
+                path,
+                self.format.extension()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// 出力に使うffmpegの引数。音声は1つ目の入力で、`cover`が指定された場合はジャケット画像を2つ目の入力として追加します。
+    pub(crate) fn ffmpeg_args(&self, cover: Option<&Path>) -> Vec<String> {
+        let mut args: Vec<String> = vec![];
+        if let Some(cover) = cover {
+            args.extend(["-i".to_string(), cover.to_string_lossy().to_string()]);
+            args.extend(
+                ["-map", "0:a", "-map", "1:v", "-c:v", "copy", "-disposition:v", "attached_pic"].map(String::from),
+            );
+            args.extend(
+                ["-metadata:s:v", "title=Album cover", "-metadata:s:v", "comment=Cover (front)"].map(String::from),
+            );
+        }
+        if let Some(metadata) = &self.metadata {
+            for (key, value) in metadata.tags() {
+                args.extend(["-metadata".to_string(), format!("{}={}", key, value)]);
+            }
+        }
+        if self.format == Format::Mp3 {
+            args.extend(["-id3v2_version", "3"].map(String::from));
+        }
+        let codec = match (self.format, self.bit_depth.unwrap_or(16)) {
+            (Format::Mp3, _) => "libmp3lame",
+            (Format::Aac, _) => "aac",
+            (Format::Vorbis, _) => "libvorbis",
+            (Format::Opus, _) => "libopus",
+            (Format::Flac, _) => "flac",
+            (Format::Wav, 24) => "pcm_s24le",
+            (Format::Wav, 32) => "pcm_s32le",
+            (Format::Wav, _) => "pcm_s16le",
+        };
+        args.extend(["-c:a".to_string(), codec.to_string()]);
+        if let Some(quality) = self.quality {
+            args.extend(["-q:a".to_string(), quality.to_string()]);
+        } else if let Some(bitrate) = self.bitrate.or(self.format.default_bitrate()) {
+            args.extend(["-b:a".to_string(), format!("{}k", bitrate)]);
+        }
+        if self.format == Format::Flac {
+            match self.bit_depth {
+                Some(24) => args.extend(["-sample_fmt", "s32", "-bits_per_raw_sample", "24"].map(String::from)),
+                _ => args.extend(["-sample_fmt", "s16"].map(String::from)),
+            }
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            args.extend(["-ar".to_string(), sample_rate.to_string()]);
+        }
+        args.extend(["-f".to_string(), self.format.muxer().to_string()]);
+        args
+    }
+}
@@ -0,0 +1,234 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::tr;
+use crate::utils::debug;
+
+pub static USER_AGENT: &str = concat!(
+    "pjsekai-soundgen-rust/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/sevenc-nanashi/pjsekai-soundgen-rust)"
+);
+
+/// ダウンロードの進捗。`id`はダウンロード毎に一意です。
+#[derive(Debug, Clone)]
+pub enum DownloadProgress {
+    Start {
+        id: String,
+        label: String,
+        total: Option<u64>,
+    },
+    Update {
+        id: String,
+        downloaded: u64,
+    },
+    Finish {
+        id: String,
+    },
+}
+
+struct Reporter<'a> {
+    id: &'a str,
+    label: &'a str,
+    sender: &'a Sender<DownloadProgress>,
+}
+
+impl Reporter<'_> {
+    fn send(&self, progress: DownloadProgress) {
+        let _ = self.sender.send(progress);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    pub connect_timeout: Duration,
+    /// レスポンスの読み込みが止まってから諦めるまでの時間。
+    pub read_timeout: Duration,
+    pub retries: u32,
+    /// 再試行の待ち時間。再試行毎に2倍になります。
+    pub retry_delay: Duration,
+    pub proxy: Option<String>,
+    pub user_agent: String,
+    pub headers: Vec<(String, String)>,
+    /// 通信せず、全てのリクエストを失敗させます。
+    pub offline: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            retries: 3,
+            retry_delay: Duration::from_millis(500),
+            proxy: None,
+            user_agent: USER_AGENT.to_string(),
+            headers: vec![],
+            offline: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    pub config: HttpConfig,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new(HttpConfig::default()).expect("HTTPクライアントの作成に失敗しました")
+    }
+}
+
+enum Failure {
+    Retryable(Error),
+    Fatal(Error),
+}
+
+impl HttpClient {
+    pub fn new(config: HttpConfig) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in config.headers.iter() {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|e| Error::invalid_input(tr!("ヘッダー名が不正です：{}", "Invalid header name: {}", e)))?,
+                HeaderValue::from_str(value).map_err(|e| {
+                    Error::invalid_input(tr!("ヘッダーの値が不正です：{}", "Invalid header value: {}", e))
+                })?,
+            );
+        }
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .user_agent(&config.user_agent)
+            .default_headers(headers);
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(
+                reqwest::Proxy::all(proxy)
+                    .map_err(|e| Error::invalid_input(tr!("プロキシが不正です：{}", "Invalid proxy: {}", e)))?,
+            );
+        }
+        let client = builder.build().map_err(|e| {
+            Error::invalid_input(tr!(
+                "HTTPクライアントの作成に失敗しました：{}",
+                "Failed to create the HTTP client: {}",
+                e
+            ))
+        })?;
+        Ok(Self { client, config })
+    }
+
+    /// GETリクエストを送り、レスポンスの本文を返します。
+    /// 通信エラーや5xxの場合は、指数バックオフで再試行します。
+    pub async fn get(&self, url: &str) -> Result<Vec<u8>> {
+        self.get_with_query(url, &[]).await
+    }
+
+    pub async fn get_with_query(&self, url: &str, query: &[(&str, &str)]) -> Result<Vec<u8>> {
+        self.request(url, query, None).await
+    }
+
+    /// `get`と同じですが、ダウンロードの進捗を`sender`に送ります。
+    pub async fn get_with_progress(
+        &self,
+        url: &str,
+        id: &str,
+        label: &str,
+        sender: Option<&Sender<DownloadProgress>>,
+    ) -> Result<Vec<u8>> {
+        let reporter = sender.map(|sender| Reporter { id, label, sender });
+        let result = self.request(url, &[], reporter.as_ref()).await;
+        if let Some(reporter) = reporter {
+            reporter.send(DownloadProgress::Finish { id: id.to_string() });
+        }
+        result
+    }
+
+    async fn request(&self, url: &str, query: &[(&str, &str)], reporter: Option<&Reporter<'_>>) -> Result<Vec<u8>> {
+        if self.config.offline {
+            return Err(Error::Offline { url: url.to_string() });
+        }
+        let mut attempt = 0;
+        loop {
+            match self.try_get(url, query, reporter).await {
+                Ok(bytes) => return Ok(bytes),
+                Err(Failure::Retryable(e)) if attempt < self.config.retries => {
+                    let delay = self.config.retry_delay * 2u32.saturating_pow(attempt);
+                    debug!(format!("retrying {} in {:?}: {}", url, delay, e));
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(Failure::Retryable(e)) | Err(Failure::Fatal(e)) => return Err(e),
+            }
+        }
+    }
+
+    pub async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str, query: &[(&str, &str)]) -> Result<T> {
+        let bytes = self.get_with_query(url, query).await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    async fn try_get(
+        &self,
+        url: &str,
+        query: &[(&str, &str)],
+        reporter: Option<&Reporter<'_>>,
+    ) -> std::result::Result<Vec<u8>, Failure> {
+        let timeout = self.config.read_timeout;
+        let mut response = tokio::time::timeout(timeout, self.client.get(url).query(query).send())
+            .await
+            .map_err(|_| Failure::Retryable(Error::Timeout { url: url.to_string() }))?
+            .map_err(|source| {
+                Failure::Retryable(Error::Network {
+                    url: url.to_string(),
+                    source,
+                })
+            })?;
+
+        let status = response.status();
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(Failure::Retryable(Error::Status {
+                status: status.as_u16(),
+                url: url.to_string(),
+            }));
+        }
+        if !status.is_success() {
+            return Err(Failure::Fatal(Error::Status {
+                status: status.as_u16(),
+                url: url.to_string(),
+            }));
+        }
+
+        let total = response.content_length();
+        if let Some(reporter) = reporter {
+            reporter.send(DownloadProgress::Start {
+                id: reporter.id.to_string(),
+                label: reporter.label.to_string(),
+                total,
+            });
+        }
+        let mut bytes = Vec::with_capacity(total.unwrap_or(0) as usize);
+        while let Some(chunk) = tokio::time::timeout(timeout, response.chunk())
+            .await
+            .map_err(|_| Failure::Retryable(Error::Timeout { url: url.to_string() }))?
+            .map_err(|source| {
+                Failure::Retryable(Error::Network {
+                    url: url.to_string(),
+                    source,
+                })
+            })?
+        {
+            bytes.extend_from_slice(&chunk);
+            if let Some(reporter) = reporter {
+                reporter.send(DownloadProgress::Update {
+                    id: reporter.id.to_string(),
+                    downloaded: bytes.len() as u64,
+                });
+            }
+        }
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,77 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::error::Error;
+use crate::tr;
+
+/// 表示に使う言語。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    Ja,
+    En,
+}
+
+static LANG: AtomicU8 = AtomicU8::new(Lang::Ja as u8);
+
+/// ライブラリとCLIのメッセージに使う言語を設定します。既定は日本語です。
+pub fn set_lang(lang: Lang) {
+    LANG.store(lang as u8, Ordering::Relaxed);
+}
+
+pub fn lang() -> Lang {
+    match LANG.load(Ordering::Relaxed) {
+        0 => Lang::Ja,
+        _ => Lang::En,
+    }
+}
+
+/// 現在の言語に合わせて、日本語と英語のどちらかを返します。
+pub fn pick<T>(ja: T, en: T) -> T {
+    match lang() {
+        Lang::Ja => ja,
+        Lang::En => en,
+    }
+}
+
+impl Lang {
+    /// `ja_JP.UTF-8`や`en-US`のようなロケールから言語を決めます。日本語以外は英語になります。
+    pub fn from_locale(locale: &str) -> Self {
+        if locale.to_ascii_lowercase().starts_with("ja") {
+            Lang::Ja
+        } else {
+            Lang::En
+        }
+    }
+}
+
+impl FromStr for Lang {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s.to_ascii_lowercase().as_str() {
+            "ja" => Ok(Lang::Ja),
+            "en" => Ok(Lang::En),
+            _ => Err(Error::invalid_input(tr!(
+                "言語が不正です：{}（ja、enのいずれかを指定してください）",
+                "Invalid language: {} (expected ja or en)",
+                s
+            ))),
+        }
+    }
+}
+
+/// 日本語と英語のメッセージを並べて書き、現在の言語のものを`format!`します。
+///
+/// ```
+/// use pjsekai_soundgen_core::tr;
+/// let message = tr!("{}件のキャッシュがあります。", "{} cache entries.", 3);
+/// ```
+#[macro_export]
+macro_rules! tr {
+    ($ja:literal, $en:literal $(, $arg:expr)* $(,)?) => {
+        match $crate::i18n::lang() {
+            $crate::i18n::Lang::Ja => format!($ja $(, $arg)*),
+            $crate::i18n::Lang::En => format!($en $(, $arg)*),
+        }
+    };
+}
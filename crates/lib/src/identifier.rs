@@ -0,0 +1,144 @@
+use crate::error::{Error, Result};
+use crate::http::HttpClient;
+use crate::registry::ServerRegistry;
+use crate::server::Server;
+use crate::tr;
+
+/// ユーザーが入力した譜面の指定。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelIdentifier {
+    pub name: String,
+    /// URLから読み取ったサーバーのURL。IDだけが指定された場合は`None`です。
+    pub server_url: Option<String>,
+}
+
+const OPEN_HOSTS: [&str; 3] = ["open.sonolus.com", "sonolus.com", "www.sonolus.com"];
+
+impl LevelIdentifier {
+    /// 譜面ID（`#chcy-xxxx`など）、WebのURL、Sonolusのリンクから譜面を特定します。
+    pub fn parse(input: &str, registry: &ServerRegistry) -> Result<Self> {
+        let input = input.trim().trim_start_matches('#');
+        if input.is_empty() {
+            return Err(Error::invalid_input(tr!("譜面IDが空です。", "The level ID is empty.")));
+        }
+
+        if !input.contains('/') {
+            if input.contains(char::is_whitespace) {
+                return Err(Error::invalid_input(tr!(
+                    "譜面IDとして認識できませんでした：{}",
+                    "Not a valid level ID: {}",
+                    input
+                )));
+            }
+            return Ok(Self {
+                name: input.to_string(),
+                server_url: None,
+            });
+        }
+
+        let url = if input.contains("://") {
+            input.to_string()
+        } else {
+            format!("https://{}", input)
+        };
+        let parsed = reqwest::Url::parse(&url).map_err(|_| {
+            Error::invalid_input(tr!("譜面のURLとして認識できませんでした：{}", "Not a valid level URL: {}", input))
+        })?;
+        let host = parsed.host_str().unwrap_or_default().to_string();
+        let segments =
+            parsed.path_segments().map(|s| s.filter(|s| !s.is_empty()).collect::<Vec<_>>()).unwrap_or_default();
+
+        // sonolus://<server>/levels/<name>
+        if parsed.scheme() == "sonolus" {
+            return Self::from_segments(&host, &segments, input);
+        }
+
+        // https://open.sonolus.com/<server>/levels/<name>、https://sonolus.com/levels/<name>
+        if OPEN_HOSTS.contains(&host.as_str()) {
+            return match segments.first() {
+                Some(&"levels") => Ok(Self {
+                    name: Self::level_name(&segments, input)?,
+                    server_url: None,
+                }),
+                Some(server) => Self::from_segments(server, &segments[1..], input),
+                None => Err(Error::invalid_input(tr!(
+                    "譜面のURLとして認識できませんでした：{}",
+                    "Not a valid level URL: {}",
+                    input
+                ))),
+            };
+        }
+
+        let origin = parsed.origin().ascii_serialization();
+
+        // <server>/sonolus/levels/<name>
+        if segments.contains(&"levels") {
+            return Self::from_segments(&origin, &segments, input);
+        }
+
+        // 登録済みサーバーのWebページ（https://cc.sevenc7c.com/charts/xxxx など）
+        let entry = registry.find_by_url(&origin).ok_or_else(|| {
+            Error::invalid_input(tr!("譜面のURLとして認識できませんでした：{}", "Not a valid level URL: {}", input))
+        })?;
+        let last = segments.last().ok_or_else(|| {
+            Error::invalid_input(tr!(
+                "URLに譜面IDが含まれていません：{}",
+                "The URL does not contain a level ID: {}",
+                input
+            ))
+        })?;
+        let name = if last.starts_with(&format!("{}-", entry.prefix)) {
+            last.to_string()
+        } else {
+            format!("{}-{}", entry.prefix, last)
+        };
+        Ok(Self {
+            name,
+            server_url: Some(entry.url.clone()),
+        })
+    }
+
+    fn from_segments(server: &str, segments: &[&str], input: &str) -> Result<Self> {
+        let position = segments.iter().position(|s| *s == "levels").ok_or_else(|| {
+            Error::invalid_input(tr!("譜面のURLとして認識できませんでした：{}", "Not a valid level URL: {}", input))
+        })?;
+        let mut server_url = if server.contains("://") {
+            server.to_string()
+        } else {
+            format!("https://{}", server)
+        };
+        for segment in segments[..position].iter().filter(|s| **s != "sonolus") {
+            server_url.push('/');
+            server_url.push_str(segment);
+        }
+        Ok(Self {
+            name: Self::level_name(&segments[position..], input)?,
+            server_url: Some(server_url),
+        })
+    }
+
+    fn level_name(segments: &[&str], input: &str) -> Result<String> {
+        segments
+            .get(1)
+            .map(|s| s.trim_start_matches('#').to_string())
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| {
+                Error::invalid_input(tr!(
+                    "URLに譜面IDが含まれていません：{}",
+                    "The URL does not contain a level ID: {}",
+                    input
+                ))
+            })
+    }
+
+    /// 譜面を配信しているサーバーを特定します。
+    pub async fn resolve_server(&self, registry: &ServerRegistry, client: &HttpClient) -> Result<Server> {
+        match &self.server_url {
+            Some(url) => match registry.find_by_url(url) {
+                Some(entry) => Ok(entry.to_server().with_client(client.clone())),
+                None => Server::from_url(url, client).await,
+            },
+            None => Ok(registry.guess(&self.name)?.with_client(client.clone())),
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use crate::{
+    cache::CacheKind,
+    error::{Context, Result},
     server::Server,
     sonolus::{LevelData, LevelInfo},
+    tr,
 };
-use anyhow::Result;
 
 pub struct Level {
     pub server: Server,
@@ -16,24 +18,31 @@ impl Level {
     }
 
     pub async fn fetch_bgm(&self, buf: &mut Vec<u8>) -> Result<()> {
-        let client = reqwest::Client::new();
-        let bgm_response = client
-            .get(self.server.merge_url(&self.info.bgm.url))
-            .send()
+        let mut bytes = self
+            .server
+            .fetch_srl_with_cache(&self.info.bgm, CacheKind::Bgm)
             .await
-            .map_err(|e| anyhow::anyhow!("BGMの取得に失敗しました。: {}", e))?;
+            .context(&tr!("BGMの取得に失敗しました。", "Failed to fetch the BGM."))?;
+        buf.append(&mut bytes);
+        Ok(())
+    }
 
-        if !bgm_response.status().is_success() {
-            return Err(anyhow::anyhow!("BGMの取得に失敗しました。"));
-        }
+    /// ジャケット画像を取得します。譜面にジャケットが無い場合は`None`を返します。
+    pub async fn fetch_cover(&self) -> Result<Option<Vec<u8>>> {
+        let Some(cover) = &self.info.cover else {
+            return Ok(None);
+        };
+        let bytes = self
+            .server
+            .fetch_srl_with_cache(cover, CacheKind::Cover)
+            .await
+            .context(&tr!("ジャケットの取得に失敗しました。", "Failed to fetch the cover."))?;
+        Ok(Some(bytes))
+    }
 
-        buf.append(
-            &mut bgm_response
-                .bytes()
-                .await
-                .map_err(|e| anyhow::anyhow!("BGMの取得に失敗しました。: {}", e))?
-                .to_vec(),
-        );
-        Ok(())
+    /// Sonolusで譜面を開くためのURL。
+    pub fn url(&self) -> String {
+        let host = self.server.url.trim_start_matches("https://").trim_start_matches("http://").trim_end_matches('/');
+        format!("https://open.sonolus.com/{}/levels/{}", host, self.info.name)
     }
 }
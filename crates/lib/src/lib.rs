@@ -1,8 +1,21 @@
+pub mod cache;
+pub mod error;
+pub mod export;
+pub mod http;
+pub mod i18n;
+pub mod identifier;
 pub mod level;
+pub mod pipeline;
+pub mod range;
+pub mod registry;
 pub mod server;
 pub mod sonolus;
 pub mod sound;
+pub mod stats;
+pub mod stems;
 pub mod synthesis;
+pub mod tempo;
 pub mod utils;
 
+pub use error::{Error, Result};
 pub use synthesis::{get_sound_timings, synthesis};
@@ -0,0 +1,146 @@
+use crate::error::{Context, Error, Result};
+use crate::level::Level;
+use crate::range::{TimeRange, Window};
+use crate::server::Server;
+use crate::sonolus::EffectInfo;
+use crate::sound::{Effect, Sound};
+use crate::synthesis::{get_sound_timings, Timing};
+use crate::tr;
+
+use std::collections::HashMap;
+use std::sync::{mpsc::Sender, Arc};
+use tokio::sync::{Mutex, OnceCell};
+use tokio::try_join;
+
+/// 読み込んだ効果音を、効果音のハッシュ毎に共有します。
+/// 同じ効果音を使う譜面を複数処理するときに、デコードを一度で済ませるために使います。
+#[derive(Debug, Clone, Default)]
+pub struct EffectStore {
+    effects: Arc<Mutex<HashMap<String, Arc<OnceCell<Effect>>>>>,
+}
+
+impl EffectStore {
+    pub async fn get_or_fetch(&self, server: &Server, effect: &EffectInfo) -> Result<Effect> {
+        let key = format!("{}-{}-{}", server.id, effect.audio.hash, effect.data.hash);
+        let cell = self.effects.lock().await.entry(key).or_default().clone();
+        cell.get_or_try_init(|| server.fetch_effect(effect.clone())).await.cloned()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BgmSource {
+    /// 譜面のBGMをダウンロードします。
+    Level,
+    /// 指定されたデータをBGMとして使います。
+    Data(Vec<u8>),
+    /// BGMを読み込みません。
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    Bgm,
+    Timing,
+    Effect,
+}
+
+#[derive(Debug, Clone)]
+pub enum PipelineEvent {
+    Start(Stage),
+    Finish(Stage),
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineOptions {
+    pub bgm: BgmSource,
+    pub bgm_volume: f32,
+    pub shift: f32,
+    pub events: Option<Sender<PipelineEvent>>,
+    pub effects: Option<EffectStore>,
+    /// 指定された場合、範囲内のタイミングだけを合成します。
+    pub range: Option<TimeRange>,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        Self {
+            bgm: BgmSource::Level,
+            bgm_volume: 1.0,
+            shift: 0.0,
+            events: None,
+            effects: None,
+            range: None,
+        }
+    }
+}
+
+/// 合成に必要なものが揃った状態。
+#[derive(Debug, Clone)]
+pub struct Prepared {
+    pub bgm: Option<Sound>,
+    pub timing: Timing,
+    pub effect: Effect,
+    /// 合成した音声とBGMを切り出す範囲。
+    pub window: Option<Window>,
+}
+
+fn send(options: &PipelineOptions, event: PipelineEvent) {
+    if let Some(events) = &options.events {
+        let _ = events.send(event);
+    }
+}
+
+/// BGMの読み込み、タイミングの計算、効果音の読み込みを並行して行います。
+/// ffmpegによるデコードはブロッキングスレッドで行われます。
+pub async fn prepare(level: &Level, options: &PipelineOptions) -> Result<Prepared> {
+    let bgm = async {
+        send(options, PipelineEvent::Start(Stage::Bgm));
+        let buf = match &options.bgm {
+            BgmSource::Level => {
+                let mut buf = vec![];
+                level.fetch_bgm(&mut buf).await?;
+                buf
+            }
+            BgmSource::Data(data) => data.clone(),
+            BgmSource::None => {
+                send(options, PipelineEvent::Finish(Stage::Bgm));
+                return Ok(None);
+            }
+        };
+        let volume = options.bgm_volume;
+        let sound = tokio::task::spawn_blocking(move || Sound::load(&buf).map(|sound| sound * volume))
+            .await
+            .map_err(|e| Error::AudioDecode(e.to_string()))
+            .and_then(|sound| sound)
+            .context(&tr!("BGMの読み込みに失敗しました。", "Failed to load the BGM."))?;
+        send(options, PipelineEvent::Finish(Stage::Bgm));
+        Ok::<_, Error>(Some(sound))
+    };
+    let timing = async {
+        send(options, PipelineEvent::Start(Stage::Timing));
+        let timing = get_sound_timings(level, options.shift).await?;
+        send(options, PipelineEvent::Finish(Stage::Timing));
+        Ok::<_, Error>(timing)
+    };
+    let effect = async {
+        send(options, PipelineEvent::Start(Stage::Effect));
+        let effect = match &options.effects {
+            Some(effects) => effects.get_or_fetch(&level.server, &level.info.engine.effect).await?,
+            None => level.server.fetch_effect(level.info.engine.effect.clone()).await?,
+        };
+        send(options, PipelineEvent::Finish(Stage::Effect));
+        Ok::<_, Error>(effect)
+    };
+
+    let (bgm, mut timing, effect) = try_join!(bgm, timing, effect)?;
+    let window = options.range.as_ref().map(|range| range.resolve(&level.data)).transpose()?;
+    if let Some(window) = &window {
+        timing = window.filter(&timing, &effect);
+    }
+    Ok(Prepared {
+        bgm,
+        timing,
+        effect,
+        window,
+    })
+}
@@ -0,0 +1,113 @@
+use crate::error::{Error, Result};
+use crate::sonolus::LevelData;
+use crate::sound::{Effect, Sound, CHANNELS};
+use crate::synthesis::Timing;
+use crate::tempo::{Position, Tempo};
+use crate::tr;
+
+/// 生成する範囲。`from`や`to`が無い場合は譜面の最初や最後までになります。
+#[derive(Debug, Clone, Default)]
+pub struct TimeRange {
+    pub from: Option<Position>,
+    pub to: Option<Position>,
+    /// `from`の前に含める秒数。
+    pub pre_roll: f32,
+    /// `to`の後に含める秒数。
+    pub post_roll: f32,
+}
+
+impl TimeRange {
+    /// 譜面のBPM変化を使って秒単位の範囲に変換します。
+    pub fn resolve(&self, data: &LevelData) -> Result<Window> {
+        if self.pre_roll < 0.0 || self.post_roll < 0.0 {
+            return Err(Error::invalid_input(tr!(
+                "プリロールとポストロールは0以上で指定してください。",
+                "Pre-roll and post-roll must not be negative."
+            )));
+        }
+        let tempo = Tempo::from_data(data)?;
+        let from = self.from.map(|from| from.seconds(&tempo, data.bgm_offset));
+        let to = self.to.map(|to| to.seconds(&tempo, data.bgm_offset));
+        if let (Some(from), Some(to)) = (from, to) {
+            if from >= to {
+                return Err(Error::invalid_input(tr!(
+                    "範囲の終わりは始まりより後にしてください：{:.3}秒 - {:.3}秒",
+                    "The end of the range must be after the start: {:.3}s - {:.3}s",
+                    from,
+                    to
+                )));
+            }
+        }
+        Ok(Window {
+            start: from.unwrap_or(0.0) - self.pre_roll,
+            end: to.map(|to| to + self.post_roll),
+        })
+    }
+}
+
+/// 秒単位の範囲。`start`はプリロールによって負になることがあります。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub start: f32,
+    pub end: Option<f32>,
+}
+
+impl Window {
+    fn contains_end(&self, time: f32) -> bool {
+        self.end.is_none_or(|end| time < end)
+    }
+
+    /// 範囲内で鳴る効果音のタイミングだけを残します。
+    /// 範囲の前に鳴り始めて、範囲内まで余韻が続く効果音も含みます。
+    pub fn filter(&self, timing: &Timing, effect: &Effect) -> Timing {
+        let duration = |clip: &str| {
+            effect
+                .audio
+                .get(clip)
+                .map_or(0.0, |sound| sound.data.len() as f32 / CHANNELS as f32 / sound.bitrate as f32)
+        };
+        Timing {
+            single: timing
+                .single
+                .iter()
+                .map(|(clip, times)| {
+                    let duration = duration(clip);
+                    let times = times
+                        .iter()
+                        .copied()
+                        .filter(|time| self.contains_end(*time) && time + duration > self.start)
+                        .collect::<Vec<_>>();
+                    (clip.clone(), times)
+                })
+                .filter(|(_, times)| !times.is_empty())
+                .collect(),
+            connect: timing
+                .connect
+                .iter()
+                .map(|(clip, ranges)| {
+                    let ranges = ranges
+                        .iter()
+                        .filter(|(start, end)| self.contains_end(*start) && *end > self.start)
+                        .map(|(start, end)| (*start, self.end.map_or(*end, |window_end| end.min(window_end))))
+                        .collect::<Vec<_>>();
+                    (clip.clone(), ranges)
+                })
+                .filter(|(_, ranges)| !ranges.is_empty())
+                .collect(),
+        }
+    }
+
+    /// 音声を範囲で切り出します。音声が無い部分は無音で埋めます。
+    pub fn trim(&self, sound: Sound) -> Sound {
+        let index = |seconds: f32| (seconds * sound.bitrate as f32).floor() as i64 * CHANNELS as i64;
+        let start = index(self.start);
+        let end = self.end.map_or(sound.data.len() as i64, index).max(start);
+        let data = (start..end)
+            .map(|i| usize::try_from(i).ok().and_then(|i| sound.data.get(i)).copied().unwrap_or(0))
+            .collect();
+        Sound {
+            data,
+            bitrate: sound.bitrate,
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::error::{Context, Error, Result};
+use crate::server::Server;
+use crate::tr;
+
+use dirs::config_dir;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerEntry {
+    pub prefix: String,
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    #[serde(default = "default_color")]
+    pub color: i32,
+}
+
+fn default_color() -> i32 {
+    0xffffff
+}
+
+impl ServerEntry {
+    pub fn to_server(&self) -> Server {
+        Server::new(&self.id, &self.name, self.color, &self.url)
+    }
+}
+
+/// プレフィックスとサーバーの対応表。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerRegistry {
+    #[serde(default)]
+    pub servers: Vec<ServerEntry>,
+}
+
+impl Default for ServerRegistry {
+    fn default() -> Self {
+        Self {
+            servers: vec![
+                ServerEntry {
+                    prefix: "ptlv".to_string(),
+                    id: "potato_leaves".to_string(),
+                    name: "Potato Leaves".to_string(),
+                    url: "https://ptlv.sevenc7c.com".to_string(),
+                    color: 0x88cb7f,
+                },
+                ServerEntry {
+                    prefix: "chcy".to_string(),
+                    id: "chart_cyanvas".to_string(),
+                    name: "Chart Cyanvas".to_string(),
+                    url: "https://cc.sevenc7c.com".to_string(),
+                    color: 0x83ccd2,
+                },
+            ],
+        }
+    }
+}
+
+impl ServerRegistry {
+    pub fn path() -> PathBuf {
+        let mut path = config_dir().unwrap_or_else(|| PathBuf::from("./config"));
+        path.push("pjsekai-soundgen-rust");
+        path.push("servers.toml");
+        path
+    }
+
+    /// 設定ファイルから読み込みます。ファイルが無い場合は既定のサーバーを返します。
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::path())
+    }
+
+    pub fn load_from(path: &std::path::Path) -> Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(Error::Config {
+                    path: path.to_path_buf(),
+                    message: e.to_string(),
+                })
+            }
+        };
+        toml::from_str(&content).map_err(|e| Error::Config {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Self::path())
+    }
+
+    pub fn save_to(&self, path: &std::path::Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let content = toml::to_string_pretty(self).map_err(|e| Error::Config {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        std::fs::write(path, content).context(&tr!(
+            "サーバー設定の保存に失敗しました（{}）",
+            "Failed to save the server settings ({})",
+            path.display()
+        ))
+    }
+
+    pub fn get(&self, prefix: &str) -> Option<&ServerEntry> {
+        self.servers.iter().find(|entry| entry.prefix == prefix)
+    }
+
+    pub fn find_by_url(&self, url: &str) -> Option<&ServerEntry> {
+        let url = url.trim_end_matches('/');
+        self.servers.iter().find(|entry| entry.url.trim_end_matches('/') == url)
+    }
+
+    /// 譜面IDのプレフィックスからサーバーを特定します。
+    pub fn guess(&self, level_name: &str) -> Result<Server> {
+        self.servers
+            .iter()
+            .find(|entry| level_name.starts_with(&format!("{}-", entry.prefix)))
+            .map(ServerEntry::to_server)
+            .ok_or_else(|| Error::UnknownServer {
+                level: level_name.to_string(),
+            })
+    }
+
+    /// サーバーを追加します。同じプレフィックスのサーバーがある場合は置き換えます。
+    pub fn insert(&mut self, entry: ServerEntry) {
+        self.servers.retain(|e| e.prefix != entry.prefix);
+        self.servers.push(entry);
+    }
+
+    pub fn remove(&mut self, prefix: &str) -> Option<ServerEntry> {
+        let index = self.servers.iter().position(|e| e.prefix == prefix)?;
+        Some(self.servers.remove(index))
+    }
+}
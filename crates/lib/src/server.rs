@@ -1,14 +1,16 @@
+use crate::cache::{Cache, CacheKind};
+use crate::error::{Context, Error, Result};
+use crate::http::{DownloadProgress, HttpClient};
 use crate::level::Level;
-use crate::sonolus::{EffectData, EffectInfo, ItemResponse, LevelData, LevelInfo, Srl};
+use crate::registry::ServerRegistry;
+use crate::sonolus::{EffectData, EffectInfo, ItemResponse, LevelData, LevelInfo, LevelListResponse, ServerInfo, Srl};
 use crate::sound::Effect;
+use crate::tr;
 use crate::utils::debug;
 
-use anyhow::Result;
-use dirs::cache_dir;
 use flate2::read::GzDecoder;
-use once_cell::sync::Lazy;
 use std::io::Read;
-use std::path::Path;
+use std::sync::mpsc::Sender;
 use tokio::try_join;
 
 #[derive(Debug, Clone)]
@@ -17,97 +19,186 @@ pub struct Server {
     pub name: String,
     pub color: i32,
     pub url: String,
+    pub client: HttpClient,
+    pub cache: Cache,
+    /// キャッシュを使うときにもハッシュを検証するかどうか。
+    pub verify_cache: bool,
+    pub download_progress: Option<Sender<DownloadProgress>>,
 }
 
-static CACHE_DIR: Lazy<Box<Path>> = Lazy::new(|| {
-    let mut path = cache_dir().or_else(|| "./cache".parse().ok()).unwrap();
-    path.push("pjsekai-soundgen-rust");
-    path.into_boxed_path()
-});
-
 impl Server {
+    pub fn new(id: &str, name: &str, color: i32, url: &str) -> Server {
+        Server {
+            id: id.to_string(),
+            name: name.to_string(),
+            color,
+            url: url.trim_end_matches('/').to_string(),
+            client: HttpClient::default(),
+            cache: Cache::default(),
+            verify_cache: false,
+            download_progress: None,
+        }
+    }
+
+    pub fn with_client(mut self, client: HttpClient) -> Server {
+        self.client = client;
+        self
+    }
+
+    pub fn with_cache(mut self, cache: Cache) -> Server {
+        self.cache = cache;
+        self
+    }
+
+    /// ダウンロードの進捗を`sender`に送るようにします。
+    pub fn with_download_progress(mut self, sender: Sender<DownloadProgress>) -> Server {
+        self.download_progress = Some(sender);
+        self
+    }
+
     pub fn guess(level_name: &str) -> Result<Server> {
-        if level_name.starts_with("ptlv-") {
-            Ok(Server {
-                id: "potato_leaves".to_string(),
-                name: "Potato Leaves".to_string(),
-                color: 0x88cb7f,
-                url: "https://ptlv.sevenc7c.com".to_string(),
-            })
-        } else if level_name.starts_with("chcy-") {
-            Ok(Server {
-                id: "chart_cyanvas".to_string(),
-                name: "Chart Cyanvas".to_string(),
-                color: 0x83ccd2,
-                url: "https://cc.sevenc7c.com".to_string(),
-            })
+        ServerRegistry::load()?.guess(level_name)
+    }
+
+    /// 任意のSonolusサーバーのURLから、`/sonolus/info`を取得してサーバーを作成します。
+    pub async fn from_url(url: &str, client: &HttpClient) -> Result<Server> {
+        let url = if url.contains("://") {
+            url.to_string()
         } else {
-            Err(anyhow::anyhow!("サーバーを特定できませんでした。"))
+            format!("https://{}", url)
+        };
+        let parsed = reqwest::Url::parse(&url)
+            .map_err(|e| Error::invalid_input(tr!("URLが不正です：{}", "Invalid URL: {}", e)))?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| Error::invalid_input(tr!("URLが不正です：{}", "Invalid URL: {}", url)))?
+            .to_string();
+        let base = url.trim_end_matches('/').trim_end_matches("/sonolus");
+
+        let info = client
+            .get_json::<ServerInfo>(&format!("{}/sonolus/info", base), &[])
+            .await
+            .context(&tr!("サーバー情報の取得に失敗しました。", "Failed to fetch the server information."))?;
+
+        Ok(Server::new(&host, &info.title, 0xffffff, base).with_client(client.clone()))
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.client.config.offline
+    }
+
+    async fn read_verified_cache(&self, srl: &Srl, key: &str) -> Result<Option<Vec<u8>>> {
+        let Some(cache) = self.cache.read(key).await else {
+            return Ok(None);
+        };
+        if !self.verify_cache || srl.verify(&cache) {
+            debug!("cache hit");
+            return Ok(Some(cache));
         }
+        debug!("cache corrupted");
+        self.cache.remove(key).await?;
+        Ok(None)
     }
 
-    async fn fetch_srl_with_cache(&self, srl: &Srl) -> Result<Vec<u8>> {
+    pub(crate) async fn fetch_srl_with_cache(&self, srl: &Srl, kind: CacheKind) -> Result<Vec<u8>> {
         let key = format!("{}-{}", self.id, srl.hash);
 
         debug!(&key);
 
-        let cache_path = CACHE_DIR.join(&key);
-        if let Ok(cache) = tokio::fs::read(&cache_path).await {
-            debug!("cache hit");
+        if let Some(cache) = self.read_verified_cache(srl, &key).await? {
             return Ok(cache);
         }
         debug!("cache miss");
+        if self.is_offline() {
+            return Err(Error::NotCached { kind, key });
+        }
+
+        // 他のプロセスが同じデータを取得している場合は、それを待ってから使う
+        let _lock = self.cache.lock(&key).await?;
+        if let Some(cache) = self.read_verified_cache(srl, &key).await? {
+            return Ok(cache);
+        }
 
-        let client = reqwest::Client::new();
         let url = self.merge_url(&srl.url);
         debug!(&url);
-        let bgm_response =
-            client.get(url).send().await.map_err(|e| anyhow::anyhow!("データの取得に失敗しました。: {}", e))?;
-
-        if !bgm_response.status().is_success() {
-            return Err(anyhow::anyhow!("データの取得に失敗しました。"));
+        let download = || async {
+            self.client
+                .get_with_progress(&url, &key, kind.label(), self.download_progress.as_ref())
+                .await
+                .context(&tr!("データの取得に失敗しました。", "Failed to fetch data."))
+        };
+        let mut bytes = download().await?;
+        if !srl.verify(&bytes) {
+            debug!("hash mismatch, retrying");
+            bytes = download().await?;
+            if !srl.verify(&bytes) {
+                return Err(Error::HashMismatch { url });
+            }
         }
 
-        let bytes = bgm_response
-            .bytes()
-            .await
-            .map_err(|e| anyhow::anyhow!("データの取得に失敗しました。: {}", e))?
-            .to_vec();
-
-        tokio::fs::create_dir_all(CACHE_DIR.as_ref()).await?;
-        tokio::fs::write(&cache_path, &bytes).await?;
+        self.cache.write(&key, &self.id, kind, &bytes).await?;
 
         Ok(bytes)
     }
 
     pub async fn fetch_level(&self, level_name: &str) -> Result<Level> {
-        let client = reqwest::Client::new();
-        let level_info = client
-            .get(format!("{}/sonolus/levels/{}", self.url, level_name).as_str())
-            .send()
-            .await
-            .map_err(|e| anyhow::anyhow!("譜面情報の取得に失敗しました。: {}", e))?
-            .json::<ItemResponse<LevelInfo>>()
-            .await
-            .map_err(|e| anyhow::anyhow!("譜面情報の取得に失敗しました。: {}", e))?
-            .item;
+        let level_info = self.fetch_level_info(level_name).await?;
         let data_bytes = &self
-            .fetch_srl_with_cache(&level_info.data)
+            .fetch_srl_with_cache(&level_info.data, CacheKind::LevelData)
             .await
-            .map_err(|e| anyhow::anyhow!("譜面データの取得に失敗しました。: {}", e))?;
+            .context(&tr!("譜面データの取得に失敗しました。", "Failed to fetch the level data."))?;
 
         let mut data_raw = GzDecoder::new(&data_bytes[..]);
         let mut buf = Vec::new();
         data_raw
             .read_to_end(&mut buf)
-            .map_err(|e| anyhow::anyhow!("譜面データの取得に失敗しました。: {}", e))?;
+            .map_err(Error::Decompress)
+            .context(&tr!("譜面データの取得に失敗しました。", "Failed to fetch the level data."))?;
 
         let level_data = serde_json::from_slice::<LevelData>(&buf[..])
-            .map_err(|e| anyhow::anyhow!("譜面データの取得に失敗しました。: {}", e))?;
+            .context(&tr!("譜面データの取得に失敗しました。", "Failed to fetch the level data."))?;
 
         Ok(Level::new(self.clone(), level_info, level_data))
     }
 
+    /// 譜面情報を取得します。
+    ///
+    /// 譜面情報のキャッシュはオフラインモード専用の控えです。オンラインの場合は常にサーバーから取得し直して
+    /// キャッシュを上書きします。譜面情報には譜面データやBGMのハッシュが含まれており、それらのキャッシュは
+    /// ハッシュをキーにしているため、譜面が更新されても古いデータを使うことはありません。
+    /// 一方、キャッシュのキーは譜面名なので、オフラインモードでは最後にオンラインで取得したときの譜面が使われます。
+    async fn fetch_level_info(&self, level_name: &str) -> Result<LevelInfo> {
+        let key = format!("{}-level-{}", self.id, level_name);
+        if self.is_offline() {
+            let cache = self.cache.read(&key).await.ok_or_else(|| Error::NotCached {
+                kind: CacheKind::LevelInfo,
+                key: key.clone(),
+            })?;
+            return serde_json::from_slice::<LevelInfo>(&cache)
+                .context(&tr!("譜面情報の取得に失敗しました。", "Failed to fetch the level information."));
+        }
+
+        let level_info = self
+            .client
+            .get_json::<ItemResponse<LevelInfo>>(&format!("{}/sonolus/levels/{}", self.url, level_name), &[])
+            .await
+            .context(&tr!("譜面情報の取得に失敗しました。", "Failed to fetch the level information."))?
+            .item;
+        self.cache.write(&key, &self.id, CacheKind::LevelInfo, &serde_json::to_vec(&level_info)?).await?;
+        Ok(level_info)
+    }
+
+    /// `/sonolus/levels/list`から譜面を検索します。`page`は0始まりです。
+    pub async fn search_levels(&self, keywords: &str, page: i32) -> Result<LevelListResponse> {
+        self.client
+            .get_json::<LevelListResponse>(
+                &format!("{}/sonolus/levels/list", self.url),
+                &[("keywords", keywords), ("page", page.to_string().as_str())],
+            )
+            .await
+            .context(&tr!("譜面の検索に失敗しました。", "Failed to search for levels."))
+    }
+
     pub fn merge_url(&self, path: &str) -> String {
         if path.starts_with("http") {
             path.to_string()
@@ -117,19 +208,55 @@ impl Server {
     }
 
     pub async fn fetch_effect(&self, effect: EffectInfo) -> Result<Effect> {
-        let (data_compressed, audio) =
-            try_join!(self.fetch_srl_with_cache(&effect.data), self.fetch_srl_with_cache(&effect.audio))
-                .map_err(|e| anyhow::anyhow!("効果音の取得に失敗しました。: {}", e))?;
+        let pcm_key =
+            format!("{}-{}-{}-pcm-{}", self.id, effect.audio.hash, effect.data.hash, Effect::decoder_settings());
+        if let Some(effect) = self.read_effect_pcm(&pcm_key).await {
+            return Ok(effect);
+        }
+        let _lock = self.cache.lock(&pcm_key).await?;
+        if let Some(effect) = self.read_effect_pcm(&pcm_key).await {
+            return Ok(effect);
+        }
+
+        let (data_compressed, audio) = try_join!(
+            self.fetch_srl_with_cache(&effect.data, CacheKind::EffectData),
+            self.fetch_srl_with_cache(&effect.audio, CacheKind::EffectAudio)
+        )
+        .context(&tr!("効果音の取得に失敗しました。", "Failed to fetch the sound effects."))?;
 
         let zip = zip::ZipArchive::new(std::io::Cursor::new(audio))
-            .map_err(|e| anyhow::anyhow!("効果音の取得に失敗しました。: {}", e))?;
+            .map_err(|e| Error::Decompress(e.into()))
+            .context(&tr!("効果音の取得に失敗しました。", "Failed to fetch the sound effects."))?;
 
         let mut data_raw = GzDecoder::new(&data_compressed[..]);
         let mut buf = Vec::new();
-        data_raw.read_to_end(&mut buf).map_err(|e| anyhow::anyhow!("効果音の取得に失敗しました。: {}", e))?;
+        data_raw
+            .read_to_end(&mut buf)
+            .map_err(Error::Decompress)
+            .context(&tr!("効果音の取得に失敗しました。", "Failed to fetch the sound effects."))?;
         let data = serde_json::from_slice::<EffectData>(&buf[..])
-            .map_err(|e| anyhow::anyhow!("効果音の取得に失敗しました。: {}", e))?;
+            .context(&tr!("効果音の取得に失敗しました。", "Failed to fetch the sound effects."))?;
+
+        let effect = tokio::task::spawn_blocking(move || Effect::new(data, zip))
+            .await
+            .map_err(|e| Error::AudioDecode(e.to_string()))
+            .context(&tr!("効果音の読み込みに失敗しました。", "Failed to load the sound effects."))??;
+        self.cache.write(&pcm_key, &self.id, CacheKind::EffectPcm, &effect.to_pcm()).await?;
+        Ok(effect)
+    }
 
-        Effect::new(data, zip)
+    async fn read_effect_pcm(&self, key: &str) -> Option<Effect> {
+        let bytes = self.cache.read(key).await?;
+        match Effect::from_pcm(&bytes) {
+            Ok(effect) => {
+                debug!("pcm cache hit");
+                Some(effect)
+            }
+            Err(_) => {
+                debug!("pcm cache corrupted");
+                let _ = self.cache.remove(key).await;
+                None
+            }
+        }
     }
 }
@@ -1,18 +1,33 @@
+use crate::error::{Error, Result};
+
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Srl {
     pub hash: String,
     pub url: String,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Srl {
+    /// データのSHA-1が`hash`と一致するかを確認します。`hash`が空の場合は常に`true`を返します。
+    pub fn verify(&self, bytes: &[u8]) -> bool {
+        self.hash.is_empty() || format!("{:x}", Sha1::digest(bytes)).eq_ignore_ascii_case(&self.hash)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LevelListResponse {
     pub items: Vec<LevelInfo>,
     #[serde(rename = "pageCount")]
     pub page_count: i32,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub title: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ItemResponse<T> {
     pub item: T,
@@ -74,6 +89,24 @@ impl LevelEntity {
         }
         None
     }
+
+    fn missing(&self, key: &str) -> Error {
+        Error::CorruptLevel {
+            archetype: self.archetype.clone(),
+            entity: self.name.clone(),
+            field: key.to_string(),
+        }
+    }
+
+    /// `get_value`と同じですが、値が無い場合は`Error::CorruptLevel`を返します。
+    pub fn require_value(&self, key: &str) -> Result<f32> {
+        self.get_value(key).ok_or_else(|| self.missing(key))
+    }
+
+    /// `get_ref`と同じですが、参照先が無い場合は`Error::CorruptLevel`を返します。
+    pub fn require_ref(&self, entities: &[LevelEntity], key: &str) -> Result<LevelEntity> {
+        self.get_ref(entities, key).ok_or_else(|| self.missing(key))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -83,25 +116,28 @@ pub struct LevelData {
     pub entities: Vec<LevelEntity>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LevelInfo {
     pub title: String,
     pub artists: String,
     pub author: String,
     pub name: String,
     pub rating: i32,
+    /// ジャケット画像。古いサーバーでは無い場合があります。
+    #[serde(default)]
+    pub cover: Option<Srl>,
     pub bgm: Srl,
     pub data: Srl,
     pub engine: EngineInfo,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EngineInfo {
     pub version: i32,
     pub effect: EffectInfo,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EffectInfo {
     pub audio: Srl,
     pub data: Srl,
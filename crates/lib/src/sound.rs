@@ -2,13 +2,17 @@ use std::collections::HashMap;
 use std::io::Read;
 
 use std::io::{Cursor, Write};
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use anyhow::{anyhow, Result};
 use once_cell::sync::Lazy;
 use zip::ZipArchive;
 
+use crate::error::{Context, Error, Result};
+use crate::export::ExportSettings;
 use crate::sonolus::EffectData;
+use crate::tr;
 
 pub static SOUND_MAP: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
     HashMap::from([
@@ -40,6 +44,61 @@ pub static SOUND_MAP: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
 pub static LOOP_SOUND_MAP: Lazy<HashMap<&'static str, &'static str>> =
     Lazy::new(|| HashMap::from([("NormalSlideConnector", "#HOLD"), ("CriticalSlideConnector", "Sekai Critical Hold")]));
 
+pub const SAMPLE_RATE: u32 = 48000;
+pub const CHANNELS: u32 = 2;
+
+static PCM_MAGIC: &[u8; 8] = b"PJSGPCM1";
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// ffmpegに渡すための一時ファイル。ドロップ時に削除されます。
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn new(bytes: &[u8]) -> Result<Self> {
+        let count = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("pjsekai-soundgen-{}-{}", std::process::id(), count));
+        std::fs::write(&path, bytes)
+            .context(&tr!("一時ファイルを作成できませんでした", "Could not create a temporary file"))?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// ffmpegを実行し、`input`を標準入力に渡して標準出力を返します。
+/// 入力は別のスレッドで書き込み、その間に標準出力と標準エラー出力を読み取るため、出力が多くても止まりません。
+/// 失敗した場合は、ffmpegのエラーか、入力を渡せなかった理由を返します。
+fn run_ffmpeg(args: &[String], input: Vec<u8>) -> std::result::Result<Vec<u8>, String> {
+    let mut child = Command::new("ffmpeg")
+        .args(["-nostats", "-loglevel", "error"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| tr!("ffmpegを起動できませんでした：{}", "Could not start ffmpeg: {}", e))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    let written = writer.join().unwrap_or_else(|_| Err(std::io::Error::other("writer thread panicked")));
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(match (stderr.lines().rev().find(|line| !line.trim().is_empty()), written) {
+            (Some(line), _) => line.to_string(),
+            (None, Err(e)) => tr!("ffmpegにデータを渡せませんでした：{}", "Could not pass the data to ffmpeg: {}", e),
+            (None, Ok(())) => tr!("ffmpegが失敗しました（{}）", "ffmpeg failed ({})", output.status),
+        });
+    }
+    written.map_err(|e| tr!("ffmpegにデータを渡せませんでした：{}", "Could not pass the data to ffmpeg: {}", e))?;
+    Ok(output.stdout)
+}
+
 #[derive(Debug, Clone)]
 pub struct Sound {
     pub data: Vec<i16>,
@@ -47,50 +106,37 @@ pub struct Sound {
 }
 
 impl Sound {
-    pub fn load(buf: &[u8]) -> Sound {
+    pub fn load(buf: &[u8]) -> Result<Sound> {
         Sound::load_with_args(buf, &[])
     }
-    pub fn load_with_args(buf: &[u8], args: &[String]) -> Sound {
-        let mut child = Command::new("ffmpeg")
-            .arg("-i")
-            .arg("-")
-            .args(args)
-            .arg("-ac")
-            .arg("2")
-            .arg("-f")
-            .arg("s16le")
-            .arg("-ar")
-            .arg("48k")
-            .arg("-")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()
-            .unwrap();
-        let local_buf = buf.to_vec();
-        let mut stdin = child.stdin.take().unwrap();
-        let thread = std::thread::spawn(move || {
-            stdin.write_all(&local_buf).unwrap();
-        });
-        let output = child.wait_with_output().unwrap();
-        thread.join().unwrap();
-        if !output.status.success() {
-            panic!("ffmpeg failed");
-        }
-        let output_buf = output.stdout;
-        Sound {
+    pub fn load_with_args(buf: &[u8], args: &[String]) -> Result<Sound> {
+        let mut ffmpeg_args = vec!["-i".to_string(), "-".to_string()];
+        ffmpeg_args.extend(args.iter().cloned());
+        ffmpeg_args.extend(
+            ["-ac", &CHANNELS.to_string(), "-f", "s16le", "-ar", &SAMPLE_RATE.to_string(), "-"].map(String::from),
+        );
+        let output_buf = run_ffmpeg(&ffmpeg_args, buf.to_vec()).map_err(Error::AudioDecode)?;
+        Ok(Sound {
             data: output_buf.chunks_exact(2).map(|a| i16::from_le_bytes([a[0], a[1]])).collect(),
-            bitrate: 48000,
-        }
+            bitrate: SAMPLE_RATE,
+        })
     }
 
     pub fn empty(bitrate: Option<u32>) -> Sound {
         Sound {
             data: vec![],
-            bitrate: bitrate.unwrap_or(48000),
+            bitrate: bitrate.unwrap_or(SAMPLE_RATE),
         }
     }
 
+    /// 末尾を無音で埋めて、`data`の長さを`len`にします。既に`len`以上の場合はそのままです。
+    pub fn pad_to(mut self, len: usize) -> Sound {
+        if self.data.len() < len {
+            self.data.resize(len, 0);
+        }
+        self
+    }
+
     pub fn overlay_at(self, other: &Sound, seconds: f32) -> Sound {
         let mut new_data = self.data.clone();
         let start_index = (seconds * self.bitrate as f32) as usize * 2;
@@ -140,32 +186,19 @@ impl Sound {
         }
     }
 
-    pub fn export(self, path: &str) {
-        let mut child = Command::new("ffmpeg")
-            .arg("-y")
-            .args(["-f", "s16le"])
-            .args(["-c:a", "pcm_s16le"])
-            .args(["-ar", self.bitrate.to_string().as_str()])
-            .args(["-ac", "2"])
-            .args(["-i", "-"])
-            .args(["-b:a", "480k"])
-            .args(["-maxrate", "480k"])
-            .args(["-bufsize", "480k"])
-            .args(["-minrate", "480k"])
-            .arg(path)
-            .stdin(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()
-            .unwrap();
-        let mut stdin = child.stdin.take().unwrap();
-        stdin
-            .write_all(&self.data.iter().flat_map(|a| a.to_le_bytes().to_vec()).collect::<Vec<u8>>())
-            .unwrap();
-        drop(stdin);
-        let output = child.wait_with_output().unwrap();
-        if !output.status.success() {
-            panic!("ffmpeg failed");
-        }
+    /// ffmpegで`settings`の形式に変換して書き出します。
+    pub fn export(self, path: &str, settings: &ExportSettings) -> Result<()> {
+        settings.validate()?;
+        let cover = settings.cover().map(TempFile::new).transpose()?;
+        let mut args = vec!["-y".to_string()];
+        args.extend(
+            ["-f", "s16le", "-c:a", "pcm_s16le", "-ar", &self.bitrate.to_string(), "-ac", "2", "-i", "-"]
+                .map(String::from),
+        );
+        args.extend(settings.ffmpeg_args(cover.as_ref().map(|cover| cover.path.as_path())));
+        args.push(path.to_string());
+        run_ffmpeg(&args, self.data.iter().flat_map(|a| a.to_le_bytes()).collect()).map_err(Error::AudioEncode)?;
+        Ok(())
     }
 
     pub fn overlay_until(self, sound: &Sound, start: f32, end: f32) -> Sound {
@@ -220,11 +253,77 @@ impl Effect {
     pub fn new(data: EffectData, mut zip: ZipArchive<Cursor<Vec<u8>>>) -> Result<Self> {
         let mut audio = HashMap::new();
         for clip in data.clips {
-            let mut file =
-                zip.by_name(&clip.filename).map_err(|_| anyhow!("効果音のファイルが見つかりませんでした"))?;
+            let mut file = zip.by_name(&clip.filename).map_err(|e| {
+                Error::Decompress(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    tr!(
+                        "効果音のアーカイブに{}（{}）がありません：{}",
+                        "The effect archive has no {} ({}): {}",
+                        clip.filename,
+                        clip.name,
+                        e
+                    ),
+                ))
+            })?;
             let mut buf = vec![];
-            file.read_to_end(&mut buf).map_err(|_| anyhow!("効果音のファイルが読み込めませんでした"))?;
-            audio.insert(clip.name, Sound::load(&buf));
+            file.read_to_end(&mut buf).map_err(Error::Decompress)?;
+            let sound = Sound::load(&buf).context(&tr!(
+                "効果音{}の読み込みに失敗しました。",
+                "Failed to load the sound effect {}.",
+                clip.name
+            ))?;
+            audio.insert(clip.name, sound);
+        }
+        Ok(Self { audio })
+    }
+
+    /// クリップ名から効果音を取得します。
+    pub fn clip(&self, name: &str) -> Result<&Sound> {
+        self.audio.get(name).ok_or_else(|| Error::UnknownClip { clip: name.to_string() })
+    }
+
+    /// デコード済みPCMのキャッシュキーに含める、デコード設定を表す文字列。
+    pub fn decoder_settings() -> String {
+        format!("s16le-{}ch-{}hz-v1", CHANNELS, SAMPLE_RATE)
+    }
+
+    /// デコード済みのPCMをキャッシュ用のバイト列にします。
+    pub fn to_pcm(&self) -> Vec<u8> {
+        let mut bytes = PCM_MAGIC.to_vec();
+        bytes.extend_from_slice(&(self.audio.len() as u32).to_le_bytes());
+        for (name, sound) in self.audio.iter() {
+            bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&sound.bitrate.to_le_bytes());
+            bytes.extend_from_slice(&(sound.data.len() as u64).to_le_bytes());
+            bytes.extend(sound.data.iter().flat_map(|a| a.to_le_bytes()));
+        }
+        bytes
+    }
+
+    pub fn from_pcm(bytes: &[u8]) -> Result<Self> {
+        let corrupt_pcm = || Error::AudioDecode(tr!("PCMのキャッシュが壊れています", "The PCM cache is corrupt"));
+        let mut cursor = Cursor::new(bytes);
+        let mut read = |len: usize| -> Result<Vec<u8>> {
+            if len > bytes.len() {
+                return Err(corrupt_pcm());
+            }
+            let mut buf = vec![0; len];
+            cursor.read_exact(&mut buf).map_err(|_| corrupt_pcm())?;
+            Ok(buf)
+        };
+        if read(PCM_MAGIC.len())? != PCM_MAGIC {
+            return Err(corrupt_pcm());
+        }
+        let count = u32::from_le_bytes(read(4)?.try_into().unwrap());
+        let mut audio = HashMap::new();
+        for _ in 0..count {
+            let name_len = u32::from_le_bytes(read(4)?.try_into().unwrap()) as usize;
+            let name = String::from_utf8(read(name_len)?).map_err(|_| corrupt_pcm())?;
+            let bitrate = u32::from_le_bytes(read(4)?.try_into().unwrap());
+            let len = u64::from_le_bytes(read(8)?.try_into().unwrap()) as usize;
+            let data = read(len.saturating_mul(2))?.chunks_exact(2).map(|a| i16::from_le_bytes([a[0], a[1]])).collect();
+            audio.insert(name, Sound { data, bitrate });
         }
         Ok(Self { audio })
     }
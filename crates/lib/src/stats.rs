@@ -0,0 +1,104 @@
+use crate::error::Result;
+use crate::level::Level;
+use crate::sound::{LOOP_SOUND_MAP, SOUND_MAP};
+use crate::synthesis::get_sound_timings;
+use crate::tempo::Tempo;
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// ノーツ密度を数える区間の長さ（秒）。
+pub const NPS_WINDOW: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BpmPoint {
+    pub beat: f32,
+    pub bpm: f32,
+    /// BGMの先頭からの秒数。
+    pub time: f32,
+}
+
+/// 譜面の統計。時間は全てBGMの先頭からの秒数です。
+#[derive(Debug, Clone, Serialize)]
+pub struct LevelStats {
+    /// アーキタイプ毎のノーツ数。
+    pub archetypes: BTreeMap<String, usize>,
+    /// 効果音毎の再生回数。ホールドは連続して鳴る区間の数です。
+    pub clips: BTreeMap<String, usize>,
+    pub bpm_changes: Vec<BpmPoint>,
+    pub notes: usize,
+    /// 最後のノーツの時刻。
+    pub duration: f32,
+    /// 任意の1秒間に含まれるノーツ数の最大値。
+    pub peak_nps: f32,
+    /// 最初のノーツから最後のノーツまでの平均。
+    pub average_nps: f32,
+    /// 全てのスライドの長さの合計。
+    pub slide_hold_time: f32,
+    pub bgm_offset: f32,
+}
+
+impl LevelStats {
+    pub async fn from_level(level: &Level) -> Result<Self> {
+        let data = &level.data;
+        let tempo = Tempo::from_data(data)?;
+        let resolve_time = |beat: f32| tempo.time_at(beat) + data.bgm_offset;
+
+        let mut archetypes: BTreeMap<String, usize> = BTreeMap::new();
+        let mut times: Vec<f32> = vec![];
+        let mut slide_hold_time = 0.0;
+        for entity in data.entities.iter() {
+            if SOUND_MAP.contains_key(entity.archetype.as_str()) {
+                *archetypes.entry(entity.archetype.clone()).or_default() += 1;
+                times.push(resolve_time(entity.require_value("#BEAT")?));
+            } else if LOOP_SOUND_MAP.contains_key(entity.archetype.as_str()) {
+                let head = entity.require_ref(&data.entities, "head")?;
+                let tail = entity.require_ref(&data.entities, "tail")?;
+                slide_hold_time +=
+                    resolve_time(tail.require_value("#BEAT")?) - resolve_time(head.require_value("#BEAT")?);
+            }
+        }
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let timing = get_sound_timings(level, 0.0).await?;
+        let clips = timing
+            .single
+            .iter()
+            .map(|(clip, times)| (clip.clone(), times.len()))
+            .chain(timing.connect.iter().map(|(clip, ranges)| (clip.clone(), ranges.len())))
+            .collect();
+
+        let mut peak = 0;
+        let mut start = 0;
+        for (end, time) in times.iter().enumerate() {
+            while times[start] <= time - NPS_WINDOW {
+                start += 1;
+            }
+            peak = peak.max(end - start + 1);
+        }
+        let span = match (times.first(), times.last()) {
+            (Some(first), Some(last)) => last - first,
+            _ => 0.0,
+        };
+
+        Ok(Self {
+            archetypes,
+            clips,
+            bpm_changes: tempo
+                .changes
+                .iter()
+                .map(|change| BpmPoint {
+                    beat: change.beat,
+                    bpm: change.bpm,
+                    time: resolve_time(change.beat),
+                })
+                .collect(),
+            notes: times.len(),
+            duration: times.last().copied().unwrap_or(0.0),
+            peak_nps: peak as f32 / NPS_WINDOW,
+            average_nps: if span > 0.0 { times.len() as f32 / span } else { 0.0 },
+            slide_hold_time,
+            bgm_offset: data.bgm_offset,
+        })
+    }
+}
@@ -0,0 +1,156 @@
+use crate::error::{Error, Result};
+use crate::sound::Sound;
+use crate::tr;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// どのグループにも含まれない効果音のステム名。
+pub static OTHER_STEM: &str = "other";
+/// BGMのステム名。
+pub static BGM_STEM: &str = "bgm";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StemGroup {
+    pub name: String,
+    /// このステムにまとめる効果音のクリップ名。
+    pub clips: Vec<String>,
+}
+
+impl StemGroup {
+    fn new(name: &str, clips: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            clips: clips.iter().map(|clip| clip.to_string()).collect(),
+        }
+    }
+}
+
+/// 効果音をステムに分ける方法。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StemGrouping {
+    pub groups: Vec<StemGroup>,
+}
+
+impl Default for StemGrouping {
+    fn default() -> Self {
+        Self::category()
+    }
+}
+
+impl StemGrouping {
+    /// ノーツの種類毎に分けます。通常と金は同じステムになります。
+    pub fn category() -> Self {
+        Self {
+            groups: vec![
+                StemGroup::new("taps", &["#PERFECT", "Sekai Critical Tap"]),
+                StemGroup::new("flicks", &["#PERFECT_ALTERNATIVE", "Sekai Critical Flick"]),
+                StemGroup::new("traces", &["Sekai Normal Trace", "Sekai Critical Trace"]),
+                StemGroup::new("ticks", &["Sekai Tick", "Sekai Critical Tick"]),
+                StemGroup::new("holds", &["#HOLD", "Sekai Critical Hold"]),
+            ],
+        }
+    }
+
+    /// ノーツの種類毎に、通常と金も分けます。
+    pub fn critical() -> Self {
+        Self {
+            groups: vec![
+                StemGroup::new("taps", &["#PERFECT"]),
+                StemGroup::new("critical-taps", &["Sekai Critical Tap"]),
+                StemGroup::new("flicks", &["#PERFECT_ALTERNATIVE"]),
+                StemGroup::new("critical-flicks", &["Sekai Critical Flick"]),
+                StemGroup::new("traces", &["Sekai Normal Trace"]),
+                StemGroup::new("critical-traces", &["Sekai Critical Trace"]),
+                StemGroup::new("ticks", &["Sekai Tick"]),
+                StemGroup::new("critical-ticks", &["Sekai Critical Tick"]),
+                StemGroup::new("holds", &["#HOLD"]),
+                StemGroup::new("critical-holds", &["Sekai Critical Hold"]),
+            ],
+        }
+    }
+
+    /// TOMLから読み込みます。
+    ///
+    /// ```toml
+    /// [[groups]]
+    /// name = "notes"
+    /// clips = ["#PERFECT", "Sekai Critical Tap"]
+    /// ```
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let grouping: Self = toml::from_str(content)
+            .map_err(|e| Error::invalid_input(tr!("ステムの設定が不正です：{}", "Invalid stem settings: {}", e)))?;
+        for (i, group) in grouping.groups.iter().enumerate() {
+            if group.name == BGM_STEM || group.name == OTHER_STEM {
+                return Err(Error::invalid_input(tr!(
+                    "ステム名{}は予約されています。",
+                    "The stem name {} is reserved.",
+                    group.name
+                )));
+            }
+            if grouping.groups[..i].iter().any(|other| other.name == group.name) {
+                return Err(Error::invalid_input(tr!(
+                    "ステム名が重複しています：{}",
+                    "Duplicate stem name: {}",
+                    group.name
+                )));
+            }
+        }
+        Ok(grouping)
+    }
+
+    /// クリップが含まれるステムの名前を返します。
+    pub fn stem_of(&self, clip: &str) -> &str {
+        self.groups
+            .iter()
+            .find(|group| group.clips.iter().any(|c| c == clip))
+            .map(|group| group.name.as_str())
+            .unwrap_or(OTHER_STEM)
+    }
+
+    /// クリップ毎の音声をステム毎にまとめ、BGMと合わせて全て同じ長さに揃えます。
+    /// グループのステムは、該当するノーツが無くても無音のステムとして含まれます。
+    pub fn split(&self, clips: HashMap<String, Sound>, bgm: Option<Sound>) -> Vec<(String, Sound)> {
+        let mut stems: Vec<(String, Sound)> =
+            self.groups.iter().map(|group| (group.name.clone(), Sound::empty(None))).collect();
+        let mut other: Option<Sound> = None;
+        for (clip, sound) in clips {
+            let name = self.stem_of(&clip);
+            if let Some((_, stem)) = stems.iter_mut().find(|(stem_name, _)| stem_name == name) {
+                *stem = std::mem::replace(stem, Sound::empty(None)).overlay_at(&sound, 0.0);
+            } else {
+                other = Some(other.unwrap_or_else(|| Sound::empty(None)).overlay_at(&sound, 0.0));
+            }
+        }
+        if let Some(other) = other {
+            stems.push((OTHER_STEM.to_string(), other));
+        }
+        if let Some(bgm) = bgm {
+            stems.push((BGM_STEM.to_string(), bgm));
+        }
+        let len = stems.iter().map(|(_, sound)| sound.data.len()).max().unwrap_or(0);
+        stems.into_iter().map(|(name, sound)| (name, sound.pad_to(len))).collect()
+    }
+}
+
+impl FromStr for StemGrouping {
+    type Err = Error;
+
+    /// `category`、`critical`、またはTOMLファイルのパスを受け付けます。
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "category" => Ok(Self::category()),
+            "critical" => Ok(Self::critical()),
+            path => {
+                let content = std::fs::read_to_string(path).map_err(|e| {
+                    Error::invalid_input(tr!(
+                        "ステムの設定を読み込めませんでした（{}）：{}（category、critical、またはファイルのパスを指定してください）", "Could not read the stem settings ({}): {} (expected category, critical or a file path)",
+                        path, e
+                    ))
+                })?;
+                Self::from_toml(&content)
+            }
+        }
+    }
+}
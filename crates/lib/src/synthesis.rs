@@ -1,11 +1,15 @@
+use crate::error::{Error, Result};
+use crate::i18n;
 use crate::level::Level;
 use crate::sound::SOUND_MAP;
 use crate::sound::{Effect, Sound, LOOP_SOUND_MAP};
+use crate::tempo::Tempo;
+use crate::tr;
 use crate::utils::debug;
 
-use anyhow::{ensure, Result};
 use itertools::Itertools;
 use once_cell::sync::Lazy;
+use std::str::FromStr;
 use std::sync;
 use std::{collections::HashMap, thread};
 
@@ -83,112 +87,91 @@ static COLOR_MAP: Lazy<HashMap<&'static str, ClipColor>> = Lazy::new(|| {
         ),
     ])
 });
-static NAME_MAP: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
+/// 進捗表示に使うクリップの名前（日本語、英語）。
+static NAME_MAP: Lazy<HashMap<&'static str, (&'static str, &'static str)>> = Lazy::new(|| {
     HashMap::from([
-        ("#PERFECT", "通常タップ"),
-        ("#PERFECT_ALTERNATIVE", "通常フリック"),
-        ("#HOLD", "通常ホールド"),
-        ("Sekai Tick", "スライド中継点"),
-        ("Sekai Critical Tap", "金タップ"),
-        ("Sekai Critical Hold", "金ホールド"),
-        ("Sekai Critical Flick", "金フリック"),
-        ("Sekai Critical Tick", "金スライド中継点"),
-        ("Sekai Normal Trace", "通常トレース"),
-        ("Sekai Critical Trace", "金トレース"),
+        ("#PERFECT", ("通常タップ", "Tap")),
+        ("#PERFECT_ALTERNATIVE", ("通常フリック", "Flick")),
+        ("#HOLD", ("通常ホールド", "Hold")),
+        ("Sekai Tick", ("スライド中継点", "Slide tick")),
+        ("Sekai Critical Tap", ("金タップ", "Critical tap")),
+        ("Sekai Critical Hold", ("金ホールド", "Critical hold")),
+        ("Sekai Critical Flick", ("金フリック", "Critical flick")),
+        ("Sekai Critical Tick", ("金スライド中継点", "Critical slide tick")),
+        ("Sekai Normal Trace", ("通常トレース", "Trace")),
+        ("Sekai Critical Trace", ("金トレース", "Critical trace")),
     ])
 });
-struct BpmChange {
-    beat: f32,
-    bpm: f32,
-}
-
 #[derive(Clone, Debug)]
 pub struct Timing {
-    single: HashMap<String, Vec<f32>>,
-    connect: HashMap<String, Vec<(f32, f32)>>,
+    pub single: HashMap<String, Vec<f32>>,
+    pub connect: HashMap<String, Vec<(f32, f32)>>,
 }
 
 #[derive(Clone, Debug)]
 pub struct ThreadInfo {
+    /// 効果音のクリップ名（`#PERFECT`など）。
+    pub clip: String,
     pub color: ClipColor,
     pub max: i32,
 }
 
 #[derive(Clone, Debug)]
 pub enum Progress {
-    Info { threads: HashMap<String, ThreadInfo> },
-    Update { id: String, current: i32 },
-    Finish { id: String, sound: Sound },
+    /// 合成は続けられるものの、結果が譜面どおりにならない問題。`clip`は原因のクリップ名です。
+    Warning {
+        clip: String,
+        message: String,
+    },
+    Info {
+        threads: HashMap<String, ThreadInfo>,
+    },
+    Update {
+        id: String,
+        current: i32,
+    },
+    Finish {
+        id: String,
+        sound: Sound,
+    },
 }
 
 pub async fn get_sound_timings(level: &Level, offset: f32) -> Result<Timing> {
     let mut timings: HashMap<String, Vec<f32>> = HashMap::new();
     let mut connect_timings: HashMap<String, Vec<(f32, f32)>> = HashMap::new();
 
-    let mut bpm_changes: Vec<BpmChange> = vec![];
-    for entity in level.data.entities.iter() {
-        if entity.archetype == "#BPM_CHANGE" {
-            bpm_changes.push(BpmChange {
-                beat: entity
-                    .get_value("#BEAT")
-                    .ok_or_else(|| anyhow::anyhow!("譜面データが壊れています：#BPM_CHANGEに#BEATがありません"))?,
-                bpm: entity
-                    .get_value("#BPM")
-                    .ok_or_else(|| anyhow::anyhow!("譜面データが壊れています：#BPM_CHANGEに#BPMがありません"))?,
-            });
-        }
-    }
-    bpm_changes.sort_by(|a, b| a.beat.partial_cmp(&b.beat).unwrap());
-    let resolve_time = |beat: f32| -> f32 {
-        let mut time = 0.0;
-        let mut last_bpm = bpm_changes[0].bpm;
-        let mut last_beat = 0.0;
-        for bpm_change in bpm_changes.iter() {
-            if bpm_change.beat > beat {
-                break;
-            }
-            time += (bpm_change.beat - last_beat) * 60.0 / last_bpm;
-            last_bpm = bpm_change.bpm;
-            last_beat = bpm_change.beat;
-        }
-        time += (beat - last_beat) * 60.0 / last_bpm;
-        time + level.data.bgm_offset + offset
-    };
+    let tempo = Tempo::from_data(&level.data)?;
+    let resolve_time = |beat: f32| -> f32 { tempo.time_at(beat) + level.data.bgm_offset + offset };
     for note in level.data.entities.iter() {
         let Some(sound_map_data) = SOUND_MAP.get(&note.archetype.as_str()) else {
             continue;
         };
         let sound_data = sound_map_data.to_string();
-        if timings.get(&sound_data).is_none() {
+        if !timings.contains_key(&sound_data) {
             timings.insert(sound_data.clone(), vec![]);
         }
-        let time = resolve_time(note.get_value("#BEAT").ok_or_else(|| {
+        let time = resolve_time(note.require_value("#BEAT").inspect_err(|_| {
             debug!(&note);
-            anyhow::anyhow!("譜面データが壊れています：#BEATがありません")
         })?);
         timings.get_mut(&sound_data).unwrap().push(time);
     }
+    let slide_mismatch = || {
+        Error::InvalidLevel(tr!(
+            "スライドの開始と終了の数が一致しません",
+            "The numbers of slide starts and ends do not match"
+        ))
+    };
     let mut slide_connectors: HashMap<String, Vec<(f32, i32)>> = HashMap::new();
     for note in level.data.entities.iter() {
         let Some(key) = LOOP_SOUND_MAP.get(&note.archetype.as_str()) else {
             continue;
         };
         let key = key.to_string();
-        let head = note
-            .get_ref(&level.data.entities, "head")
-            .ok_or_else(|| anyhow::anyhow!("譜面データが壊れています：SlideConnectorにheadがありません"))?;
-        let tail = note
-            .get_ref(&level.data.entities, "tail")
-            .ok_or_else(|| anyhow::anyhow!("譜面データが壊れています：SlideConnectorにtailがありません"))?;
-        let head_time = resolve_time(
-            head.get_value("#BEAT")
-                .ok_or_else(|| anyhow::anyhow!("譜面データが壊れています：SlideConnectorのheadに#BEATがありません"))?,
-        );
-        let tail_time = resolve_time(
-            tail.get_value("#BEAT")
-                .ok_or_else(|| anyhow::anyhow!("譜面データが壊れています：SlideConnectorのtailに#BEATがありません"))?,
-        );
-        if slide_connectors.get(&key).is_none() {
+        let head = note.require_ref(&level.data.entities, "head")?;
+        let tail = note.require_ref(&level.data.entities, "tail")?;
+        let head_time = resolve_time(head.require_value("#BEAT")?);
+        let tail_time = resolve_time(tail.require_value("#BEAT")?);
+        if !slide_connectors.contains_key(&key) {
             slide_connectors.insert(key.clone(), vec![]);
         }
         slide_connectors.get_mut(&key).unwrap().push((head_time, 1));
@@ -206,7 +189,7 @@ pub async fn get_sound_timings(level: &Level, offset: f32) -> Result<Timing> {
         grouped_changes.sort_by(|(time1, _), (time2, _)| time1.partial_cmp(time2).unwrap());
 
         for (time, changes) in &grouped_changes {
-            if connect_timings.get(key).is_none() {
+            if !connect_timings.contains_key(key) {
                 connect_timings.insert(key.clone(), vec![]);
             }
             let time = *time;
@@ -223,13 +206,13 @@ pub async fn get_sound_timings(level: &Level, offset: f32) -> Result<Timing> {
             } else if slide_count == 1 && change > 0 {
                 timing.push((time, -1.0));
             }
-            ensure!(slide_count >= 0, "譜面データが壊れています：スライドの開始と終了の数が一致しません");
+            if slide_count < 0 {
+                return Err(slide_mismatch());
+            }
+        }
+        if slide_count != 0 || connect_timings.get(key).unwrap().last().unwrap().1 == -1.0 {
+            return Err(slide_mismatch());
         }
-        ensure!(slide_count == 0, "譜面データが壊れています：スライドの開始と終了の数が一致しません");
-        ensure!(
-            connect_timings.get(key).unwrap().last().unwrap().1 != -1.0,
-            "譜面データが壊れています：スライドの開始と終了の数が一致しません"
-        );
     }
     timings.values_mut().for_each(|v| {
         v.sort_by(|a, b| a.partial_cmp(b).unwrap());
@@ -241,17 +224,121 @@ pub async fn get_sound_timings(level: &Level, offset: f32) -> Result<Timing> {
     })
 }
 
-pub async fn synthesis(timing: &Timing, effect: &Effect, notes_per_thread: usize) -> sync::mpsc::Receiver<Progress> {
+/// 効果音のデータに無いクリップを譜面が使っている場合の扱い。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownClipPolicy {
+    /// そのクリップを鳴らさずに合成します。
+    Skip,
+    /// `#PERFECT`で代用します。
+    #[default]
+    Substitute,
+    /// `Error::UnknownClip`を返します。
+    Fail,
+}
+
+impl FromStr for UnknownClipPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(UnknownClipPolicy::Skip),
+            "substitute" => Ok(UnknownClipPolicy::Substitute),
+            "fail" => Ok(UnknownClipPolicy::Fail),
+            _ => Err(Error::invalid_input(tr!(
+                "不明なSEの扱いが不正です：{}（skip、substitute、failのいずれかを指定してください）",
+                "Invalid unknown clip policy: {} (expected skip, substitute or fail)",
+                s
+            ))),
+        }
+    }
+}
+
+/// `COLOR_MAP`に無いクリップの色。
+const FALLBACK_COLOR: ClipColor = ClipColor {
+    fg: "white",
+    bg: "white",
+};
+
+/// 合成に使うクリップの音声と表示。
+struct ResolvedClip {
+    sound: Sound,
+    color: ClipColor,
+    name: String,
+}
+
+/// クリップの音声と表示用の色、名前を決めます。
+/// 効果音のデータに無いクリップは`policy`に従って扱い、警告を`Progress::Warning`として送ります。
+/// スキップする場合は`None`を返します。
+fn resolve_clip(
+    effect: &Effect,
+    clip: &str,
+    policy: UnknownClipPolicy,
+    tx: &sync::mpsc::Sender<Progress>,
+) -> Result<Option<ResolvedClip>> {
+    let sound = match effect.clip(clip) {
+        Ok(sound) => sound.clone(),
+        Err(err) => {
+            let message = match policy {
+                UnknownClipPolicy::Fail => return Err(err),
+                UnknownClipPolicy::Skip => tr!("{}。このSEは鳴らしません。", "{}. It will not be played.", err),
+                UnknownClipPolicy::Substitute => {
+                    tr!("{}。#PERFECTで代用します。", "{}. Using #PERFECT instead.", err)
+                }
+            };
+            tx.send(Progress::Warning {
+                clip: clip.to_string(),
+                message,
+            })
+            .unwrap();
+            match policy {
+                UnknownClipPolicy::Substitute => effect.clip("#PERFECT")?.clone(),
+                _ => return Ok(None),
+            }
+        }
+    };
+    Ok(Some(ResolvedClip {
+        sound,
+        color: COLOR_MAP.get(clip).cloned().unwrap_or(FALLBACK_COLOR),
+        name: NAME_MAP.get(clip).map_or_else(|| clip.to_string(), |(ja, en)| i18n::pick(*ja, *en).to_string()),
+    }))
+}
+
+/// 効果音を合成するスレッドを立て、進捗を受け取る`Receiver`を返します。
+/// 最初に`Progress::Warning`が0個以上、次に`Progress::Info`が送られます。
+/// `notes_per_thread`が0の場合は`Error::InvalidInput`を返します。
+pub async fn synthesis(
+    timing: &Timing,
+    effect: &Effect,
+    notes_per_thread: usize,
+    unknown_clip: UnknownClipPolicy,
+) -> Result<sync::mpsc::Receiver<Progress>> {
+    if notes_per_thread == 0 {
+        return Err(Error::invalid_input(tr!(
+            "スレッド毎のノーツ数は1以上にしてください。",
+            "The number of notes per thread must be at least 1."
+        )));
+    }
     let (tx, rx) = sync::mpsc::channel::<Progress>();
+    let mut clips: HashMap<String, ResolvedClip> = HashMap::new();
+    for clip in timing.single.keys().chain(timing.connect.keys()) {
+        if let Some(resolved) = resolve_clip(effect, clip, unknown_clip, &tx)? {
+            clips.insert(clip.clone(), resolved);
+        }
+    }
     let timing = timing.clone();
-    let effect = effect.clone();
 
     thread::spawn(move || {
         let mut thread_infos: HashMap<String, ThreadInfo> = HashMap::new();
         let mut threads: Vec<thread::JoinHandle<()>> = vec![];
         for (sound_name, timings) in timing.single.iter() {
-            let thread_count = (timings.len() + notes_per_thread - 1) / notes_per_thread;
-            let notes_per_thread = (timings.len() + thread_count - 1) / thread_count;
+            let Some(clip) = clips.get(sound_name) else {
+                continue;
+            };
+            if timings.is_empty() {
+                continue;
+            }
+            let thread_count = timings.len().div_ceil(notes_per_thread);
+            let notes_per_thread = timings.len().div_ceil(thread_count);
             for i in 0..thread_count {
                 let start = i * notes_per_thread;
                 let end = if i == thread_count - 1 {
@@ -260,29 +347,17 @@ pub async fn synthesis(timing: &Timing, effect: &Effect, notes_per_thread: usize
                     std::cmp::min((i + 1) * notes_per_thread, timings.len())
                 };
                 let timings = timings[start..end].to_vec();
-                let effect = effect.clone();
                 let tx = tx.clone();
                 debug!(&sound_name);
-                let sound = effect
-                    .audio
-                    .get(sound_name)
-                    .unwrap_or_else(|| panic!("不明なSEです：{}。Issueに報告してください。", sound_name))
-                    .clone();
-                let color = COLOR_MAP
-                    .get(&sound_name.as_str())
-                    .unwrap_or_else(|| panic!("不明なSEです：{}。Issueに報告してください。", sound_name))
-                    .to_owned();
-                let name = NAME_MAP
-                    .get(&sound_name.as_str())
-                    .unwrap_or_else(|| panic!("不明なSEです：{}。Issueに報告してください。", sound_name))
-                    .to_owned();
+                let sound = clip.sound.clone();
 
-                let id = format!("{} ({})", name, i + 1);
+                let id = format!("{} ({})", clip.name, i + 1);
 
                 thread_infos.insert(
                     id.clone(),
                     ThreadInfo {
-                        color: color.clone(),
+                        clip: sound_name.clone(),
+                        color: clip.color.clone(),
                         max: timings.len() as i32,
                     },
                 );
@@ -303,29 +378,20 @@ pub async fn synthesis(timing: &Timing, effect: &Effect, notes_per_thread: usize
             }
         }
         for (sound_name, timings) in timing.connect.iter() {
+            let Some(clip) = clips.get(sound_name) else {
+                continue;
+            };
             let timings = timings.clone();
-            let effect = effect.clone();
             let tx = tx.clone();
-            let sound = effect
-                .audio
-                .get(sound_name)
-                .unwrap_or_else(|| panic!("不明なSEです：{}。Issueに報告してください。", sound_name))
-                .clone();
-            let color = COLOR_MAP
-                .get(&sound_name.as_str())
-                .unwrap_or_else(|| panic!("不明なSEです：{}。Issueに報告してください。", sound_name))
-                .to_owned();
-            let name = NAME_MAP
-                .get(&sound_name.as_str())
-                .unwrap_or_else(|| panic!("不明なSEです：{}。Issueに報告してください。", sound_name))
-                .to_owned();
+            let sound = clip.sound.clone();
 
-            let id = name.to_string();
+            let id = clip.name.clone();
 
             thread_infos.insert(
                 id.clone(),
                 ThreadInfo {
-                    color: color.clone(),
+                    clip: sound_name.clone(),
+                    color: clip.color.clone(),
                     max: timings.len() as i32,
                 },
             );
@@ -349,5 +415,5 @@ pub async fn synthesis(timing: &Timing, effect: &Effect, notes_per_thread: usize
         }
     });
 
-    rx
+    Ok(rx)
 }
@@ -0,0 +1,101 @@
+use crate::error::{Error, Result};
+use crate::sonolus::LevelData;
+use crate::tr;
+
+use serde::Serialize;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BpmChange {
+    pub beat: f32,
+    pub bpm: f32,
+}
+
+/// 譜面のBPM変化から、拍を秒に変換します。
+#[derive(Debug, Clone)]
+pub struct Tempo {
+    /// 拍の順に並んだBPM変化。最初の要素は常に存在します。
+    pub changes: Vec<BpmChange>,
+}
+
+impl Tempo {
+    pub fn from_data(data: &LevelData) -> Result<Self> {
+        let mut changes: Vec<BpmChange> = vec![];
+        for entity in data.entities.iter() {
+            if entity.archetype == "#BPM_CHANGE" {
+                changes.push(BpmChange {
+                    beat: entity.require_value("#BEAT")?,
+                    bpm: entity.require_value("#BPM")?,
+                });
+            }
+        }
+        if changes.is_empty() {
+            return Err(Error::InvalidLevel(tr!("#BPM_CHANGEがありません", "The level has no #BPM_CHANGE")));
+        }
+        changes.sort_by(|a, b| a.beat.partial_cmp(&b.beat).unwrap());
+        Ok(Self { changes })
+    }
+
+    /// 拍を、0拍目からの秒数に変換します。`bgmOffset`は含みません。
+    pub fn time_at(&self, beat: f32) -> f32 {
+        let mut time = 0.0;
+        let mut last_bpm = self.changes[0].bpm;
+        let mut last_beat = 0.0;
+        for change in self.changes.iter() {
+            if change.beat > beat {
+                break;
+            }
+            time += (change.beat - last_beat) * 60.0 / last_bpm;
+            last_bpm = change.bpm;
+            last_beat = change.beat;
+        }
+        time + (beat - last_beat) * 60.0 / last_bpm
+    }
+}
+
+/// 4/4拍子として、1小節の拍数。
+pub const BEATS_PER_MEASURE: f32 = 4.0;
+
+/// 譜面上の位置。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    /// BGMの先頭からの秒数。
+    Seconds(f32),
+    /// 0始まりの拍。
+    Beat(f32),
+    /// 0始まりの小節。
+    Measure(f32),
+}
+
+impl Position {
+    /// BGMの先頭からの秒数に変換します。
+    pub fn seconds(&self, tempo: &Tempo, bgm_offset: f32) -> f32 {
+        match self {
+            Position::Seconds(seconds) => *seconds,
+            Position::Beat(beat) => tempo.time_at(*beat) + bgm_offset,
+            Position::Measure(measure) => tempo.time_at(measure * BEATS_PER_MEASURE) + bgm_offset,
+        }
+    }
+}
+
+impl FromStr for Position {
+    type Err = Error;
+
+    /// `12.5`（秒）、`b:32`（拍）、`m:8`（小節）を受け付けます。
+    fn from_str(s: &str) -> Result<Self> {
+        let (constructor, value): (fn(f32) -> Position, &str) = if let Some(beat) = s.strip_prefix("b:") {
+            (Position::Beat, beat)
+        } else if let Some(measure) = s.strip_prefix("m:") {
+            (Position::Measure, measure)
+        } else {
+            (Position::Seconds, s)
+        };
+        value.trim().parse::<f32>().ok().filter(|value| value.is_finite()).map(constructor).ok_or_else(|| {
+            Error::invalid_input(tr!(
+                "位置が不正です：{}（秒、b:拍、m:小節で指定してください）",
+                "Invalid position: {} (use seconds, b:BEAT or m:MEASURE)",
+                s
+            ))
+        })
+    }
+}
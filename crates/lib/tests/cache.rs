@@ -0,0 +1,142 @@
+use pjsekai_soundgen_core::cache::{Cache, CacheEntry, CacheKind};
+use std::path::Path;
+
+/// `key`のメタデータを書き換え、最終使用時刻を`last_access`にします。
+fn touch(dir: &Path, key: &str, last_access: u64) {
+    let path = dir.join(format!("{}.meta.json", key));
+    let mut entry = serde_json::from_slice::<CacheEntry>(&std::fs::read(&path).unwrap()).unwrap();
+    entry.last_access = last_access;
+    std::fs::write(path, serde_json::to_vec(&entry).unwrap()).unwrap();
+}
+
+fn keys(entries: &[CacheEntry]) -> Vec<&str> {
+    entries.iter().map(|entry| entry.key.as_str()).collect()
+}
+
+#[tokio::test]
+async fn prunes_least_recently_used_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = Cache::new(dir.path());
+    for (key, last_access) in [("a", 300), ("b", 100), ("c", 200)] {
+        cache.write(key, "mock", CacheKind::LevelData, &[0; 10]).await.unwrap();
+        touch(dir.path(), key, last_access);
+    }
+
+    let removed = cache.prune(20, None).await.unwrap();
+    assert_eq!(keys(&removed), ["b"]);
+    assert_eq!(keys(&cache.entries().await.unwrap()), ["a", "c"]);
+    assert!(!dir.path().join("b").exists());
+    assert!(!dir.path().join("b.meta.json").exists());
+
+    let removed = cache.prune(20, None).await.unwrap();
+    assert!(removed.is_empty());
+
+    let removed = cache.prune(0, Some("c")).await.unwrap();
+    assert_eq!(keys(&removed), ["a"]);
+    assert_eq!(keys(&cache.entries().await.unwrap()), ["c"]);
+}
+
+#[tokio::test]
+async fn keeps_written_entry_when_over_max_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut cache = Cache::new(dir.path());
+    cache.write("old", "mock", CacheKind::LevelData, &[0; 10]).await.unwrap();
+    touch(dir.path(), "old", 100);
+
+    cache.max_size = Some(15);
+    cache.write("new", "mock", CacheKind::LevelData, &[0; 20]).await.unwrap();
+
+    let entries = cache.entries().await.unwrap();
+    assert_eq!(keys(&entries), ["new"]);
+    assert_eq!(entries[0].size, 20);
+    assert_eq!(cache.read("new").await.unwrap(), [0; 20]);
+}
+
+#[tokio::test]
+async fn skips_temp_and_lock_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = Cache::new(dir.path());
+    let lock = cache.lock("a").await.unwrap();
+    cache.write("a", "mock", CacheKind::LevelData, b"data").await.unwrap();
+    drop(lock);
+    std::fs::write(dir.path().join("b.1234-0.tmp"), b"partial").unwrap();
+
+    let mut files = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|file| file.unwrap().file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    files.sort();
+    assert_eq!(files, ["a", "a.lock", "a.meta.json", "b.1234-0.tmp"]);
+    assert_eq!(keys(&cache.entries().await.unwrap()), ["a"]);
+    assert_eq!(cache.total_size().await.unwrap(), 4);
+}
+
+#[tokio::test]
+async fn waits_for_lock_release() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = Cache::new(dir.path());
+    let lock = cache.lock("a").await.unwrap();
+
+    let waiting = tokio::spawn({
+        let cache = cache.clone();
+        async move { cache.lock("a").await.unwrap() }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(!waiting.is_finished());
+
+    drop(lock);
+    tokio::time::timeout(std::time::Duration::from_secs(5), waiting).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn removes_unused_lock_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = Cache::new(dir.path());
+    for (key, last_access) in [("a", 300), ("b", 100), ("c", 200)] {
+        drop(cache.lock(key).await.unwrap());
+        cache.write(key, "mock", CacheKind::Bgm, &[0; 10]).await.unwrap();
+        touch(dir.path(), key, last_access);
+    }
+    drop(cache.lock("orphan").await.unwrap());
+
+    let held = cache.lock("c").await.unwrap();
+    cache.prune(10, None).await.unwrap();
+    assert!(!dir.path().join("b.lock").exists());
+    assert!(dir.path().join("c.lock").exists());
+    assert!(dir.path().join("a.lock").exists());
+
+    assert_eq!(cache.clear().await.unwrap(), 1);
+    assert!(!dir.path().join("a.lock").exists());
+    assert!(!dir.path().join("orphan.lock").exists());
+    assert!(dir.path().join("c.lock").exists());
+    drop(held);
+
+    cache.clear().await.unwrap();
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn keeps_unsafe_keys_inside_cache_dir() {
+    let root = tempfile::tempdir().unwrap();
+    let dir = root.path().join("cache");
+    let cache = Cache::new(&dir);
+    let keys_to_write = ["mock-level-../../evil", "a/b", "..", "CON", "x.meta.json", "x", "名前 付き"];
+    for (i, key) in keys_to_write.iter().enumerate() {
+        drop(cache.lock(key).await.unwrap());
+        cache.write(key, "mock", CacheKind::LevelInfo, &[i as u8]).await.unwrap();
+        assert!(cache.path(key).starts_with(&dir), "{}", key);
+    }
+
+    assert_eq!(std::fs::read_dir(root.path()).unwrap().count(), 1);
+    let mut listed = cache.entries().await.unwrap().into_iter().map(|entry| entry.key).collect::<Vec<_>>();
+    listed.sort();
+    let mut expected = keys_to_write.map(String::from).to_vec();
+    expected.sort();
+    assert_eq!(listed, expected);
+    for (i, key) in keys_to_write.iter().enumerate() {
+        assert_eq!(cache.read(key).await.unwrap(), [i as u8], "{}", key);
+    }
+
+    assert_eq!(cache.clear().await.unwrap(), keys_to_write.len());
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+}
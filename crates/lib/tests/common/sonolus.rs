@@ -0,0 +1,210 @@
+use super::{MockServer, Response};
+
+use flate2::{write::GzEncoder, Compression};
+use pjsekai_soundgen_core::{
+    cache::Cache,
+    http::{HttpClient, HttpConfig},
+    level::Level,
+    server::Server,
+};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+
+pub static LEVEL_NAME: &str = "mock-level";
+
+pub fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+pub fn sha1(bytes: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(bytes))
+}
+
+/// 16bit、ステレオ、48kHzの無音に近いWAVを作ります。
+pub fn wav(samples: usize) -> Vec<u8> {
+    let data = (0..samples * 2).flat_map(|i| ((i % 64) as i16 * 64).to_le_bytes()).collect::<Vec<u8>>();
+    let mut bytes = vec![];
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&48000u32.to_le_bytes());
+    bytes.extend_from_slice(&(48000u32 * 4).to_le_bytes());
+    bytes.extend_from_slice(&4u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&data);
+    bytes
+}
+
+fn entity(archetype: &str, name: Option<&str>, data: Value) -> Value {
+    json!({ "archetype": archetype, "name": name, "data": data })
+}
+
+fn beat(archetype: &str, name: Option<&str>, beat: f32) -> Value {
+    entity(archetype, name, json!([{ "name": "#BEAT", "value": beat }]))
+}
+
+/// 120BPMで、タップ3つ、金タップ1つ、スライド1つを含む譜面。
+pub fn level_data() -> Value {
+    json!({
+        "bgmOffset": 0.0,
+        "entities": [
+            entity("Initialization", None, json!([])),
+            entity("#BPM_CHANGE", None, json!([{ "name": "#BEAT", "value": 0.0 }, { "name": "#BPM", "value": 120.0 }])),
+            beat("NormalTapNote", None, 1.0),
+            beat("NormalTapNote", None, 2.0),
+            beat("NormalTapNote", None, 3.0),
+            beat("CriticalTapNote", None, 4.0),
+            beat("NormalSlideStartNote", Some("start"), 5.0),
+            beat("NormalSlideEndNote", Some("end"), 6.0),
+            entity(
+                "NormalSlideConnector",
+                None,
+                json!([{ "name": "head", "ref": "start" }, { "name": "tail", "ref": "end" }])
+            ),
+        ],
+    })
+}
+
+pub static CLIPS: [&str; 3] = ["#PERFECT", "Sekai Critical Tap", "#HOLD"];
+
+pub fn effect_data() -> Value {
+    json!({
+        "clips": CLIPS
+            .iter()
+            .enumerate()
+            .map(|(i, name)| json!({ "name": name, "filename": format!("{}.wav", i) }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+pub fn effect_audio() -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+    for (i, _) in CLIPS.iter().enumerate() {
+        zip.start_file(format!("{}.wav", i), zip::write::FileOptions::default()).unwrap();
+        zip.write_all(&wav(2400)).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+/// Sonolusサーバーの代わりになるテスト用サーバー。
+pub struct MockSonolus {
+    pub server: MockServer,
+}
+
+pub struct Fixtures {
+    pub level_data: Vec<u8>,
+    pub effect_data: Vec<u8>,
+    pub effect_audio: Vec<u8>,
+    pub bgm: Vec<u8>,
+    pub cover: Vec<u8>,
+}
+
+impl Default for Fixtures {
+    fn default() -> Self {
+        Self {
+            level_data: gzip(level_data().to_string().as_bytes()),
+            effect_data: gzip(effect_data().to_string().as_bytes()),
+            effect_audio: effect_audio(),
+            bgm: wav(48000 * 4),
+            cover: b"\x89PNG\r\n\x1a\nmock cover".to_vec(),
+        }
+    }
+}
+
+impl MockSonolus {
+    pub async fn start() -> Self {
+        Self::start_with(Fixtures::default()).await
+    }
+
+    pub async fn start_with(fixtures: Fixtures) -> Self {
+        Self::start_with_hashes(fixtures, HashMap::new()).await
+    }
+
+    /// `hashes`で、譜面情報に載せるハッシュを上書きできます。
+    pub async fn start_with_hashes(fixtures: Fixtures, hashes: HashMap<&'static str, String>) -> Self {
+        let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+        let mut srl = |name: &'static str, bytes: Vec<u8>| {
+            let hash = hashes.get(name).cloned().unwrap_or_else(|| sha1(&bytes));
+            files.insert(format!("/repository/{}", name), bytes);
+            json!({ "hash": hash, "url": format!("/repository/{}", name) })
+        };
+        let level_info = json!({
+            "name": LEVEL_NAME,
+            "title": "Mock Song",
+            "artists": "Mock Artist",
+            "author": "Mock Author",
+            "rating": 30,
+            "cover": srl("cover", fixtures.cover),
+            "bgm": srl("bgm", fixtures.bgm),
+            "data": srl("level_data", fixtures.level_data),
+            "engine": {
+                "version": 1,
+                "effect": {
+                    "audio": srl("effect_audio", fixtures.effect_audio),
+                    "data": srl("effect_data", fixtures.effect_data),
+                },
+            },
+        });
+        files.insert("/sonolus/info".to_string(), json!({ "title": "Mock Server" }).to_string().into_bytes());
+        files.insert(format!("/sonolus/levels/{}", LEVEL_NAME), json!({ "item": level_info }).to_string().into_bytes());
+        files.insert(
+            "/sonolus/levels/list".to_string(),
+            json!({ "items": [level_info], "pageCount": 1 }).to_string().into_bytes(),
+        );
+
+        let server = MockServer::start(move |request| {
+            let path = request.path.split('?').next().unwrap_or_default();
+            match files.get(path) {
+                Some(bytes) => Response::ok(bytes.clone()),
+                None => Response::status(404),
+            }
+        })
+        .await;
+        Self { server }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.server.url
+    }
+}
+
+/// モックサーバーに接続し、`cache_dir`をキャッシュに使う`Server`。再試行の待ち時間は短くしてあります。
+pub fn server(url: &str, cache_dir: &Path) -> Server {
+    Server::new("mock", "Mock", 0xffffff, url).with_cache(Cache::new(cache_dir)).with_client(
+        HttpClient::new(HttpConfig {
+            retry_delay: Duration::from_millis(10),
+            ..HttpConfig::default()
+        })
+        .unwrap(),
+    )
+}
+
+/// モックサーバーから取得した譜面。`mock`と`cache`は、これを破棄するまで使えます。
+pub struct FixtureLevel {
+    pub mock: MockSonolus,
+    pub cache: TempDir,
+    pub level: Level,
+}
+
+pub async fn fixture_level() -> FixtureLevel {
+    fixture_level_with(Fixtures::default()).await
+}
+
+pub async fn fixture_level_with(fixtures: Fixtures) -> FixtureLevel {
+    let mock = MockSonolus::start_with(fixtures).await;
+    let cache = tempfile::tempdir().unwrap();
+    let level = server(mock.url(), cache.path()).fetch_level(LEVEL_NAME).await.unwrap();
+    FixtureLevel { mock, cache, level }
+}
@@ -0,0 +1,371 @@
+mod common;
+
+use common::sonolus::{
+    fixture_level, fixture_level_with, gzip, level_data, server, Fixtures, MockSonolus, CLIPS, LEVEL_NAME,
+};
+use pjsekai_soundgen_core::{
+    cache::{Cache, CacheKind},
+    export::{ExportSettings, Format, Metadata},
+    http::{HttpClient, HttpConfig},
+    i18n::{set_lang, Lang},
+    pipeline::{self, BgmSource, PipelineOptions},
+    range::{TimeRange, Window},
+    server::Server,
+    sonolus::{EffectClip, EffectData},
+    sound::{Effect, Sound},
+    stats::LevelStats,
+    synthesis::{get_sound_timings, synthesis, Progress, Timing, UnknownClipPolicy},
+    tempo::Position,
+    Error,
+};
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+
+fn offline(server: Server) -> Server {
+    let client = HttpClient::new(HttpConfig {
+        offline: true,
+        ..server.client.config.clone()
+    })
+    .unwrap();
+    server.with_client(client)
+}
+
+fn has_ffmpeg() -> bool {
+    Command::new("ffmpeg").arg("-version").stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok()
+}
+
+fn test_effect() -> Effect {
+    Effect {
+        audio: CLIPS
+            .iter()
+            .map(|name| {
+                (
+                    name.to_string(),
+                    Sound {
+                        data: vec![100; 4800],
+                        bitrate: 48000,
+                    },
+                )
+            })
+            .collect(),
+    }
+}
+
+async fn collect(timing: &Timing, effect: &Effect) -> HashMap<String, Sound> {
+    let rx = synthesis(timing, effect, 2, UnknownClipPolicy::default()).await.unwrap();
+    let Progress::Info { threads } = rx.recv().unwrap() else {
+        panic!("first progress should be Info");
+    };
+    let mut sounds = HashMap::new();
+    while sounds.len() < threads.len() {
+        if let Progress::Finish { id, sound } = rx.recv().unwrap() {
+            sounds.insert(id, sound);
+        }
+    }
+    sounds
+}
+
+#[tokio::test]
+async fn fetches_level_from_mock_server() {
+    let fixture = fixture_level().await;
+    let level = &fixture.level;
+
+    assert_eq!(level.info.title, "Mock Song");
+    assert_eq!(level.info.rating, 30);
+    assert_eq!(level.data.entities.len(), 9);
+    assert!(fixture.mock.server.requests().iter().any(|r| r.path == format!("/sonolus/levels/{}", LEVEL_NAME)));
+}
+
+#[tokio::test]
+async fn fetches_cover_and_builds_metadata() {
+    let fixture = fixture_level().await;
+    let level = &fixture.level;
+    let cover = level.fetch_cover().await.unwrap().unwrap();
+    let metadata = Metadata::from_level(level).with_cover(cover.clone());
+
+    assert_eq!(cover, Fixtures::default().cover);
+    assert_eq!(metadata.title.as_deref(), Some("Mock Song (Lv. 30)"));
+    assert_eq!(metadata.artist.as_deref(), Some("Mock Artist"));
+    let comment = metadata.comment.unwrap();
+    assert!(comment.contains("Mock Author"));
+    assert!(comment.ends_with(&format!("/levels/{}", LEVEL_NAME)));
+}
+
+#[tokio::test]
+async fn resolves_server_from_url() {
+    let mock = MockSonolus::start().await;
+
+    let server = Server::from_url(mock.url(), &HttpClient::default()).await.unwrap();
+
+    assert_eq!(server.name, "Mock Server");
+    assert_eq!(server.url, mock.url());
+}
+
+#[tokio::test]
+async fn searches_levels() {
+    let mock = MockSonolus::start().await;
+    let cache = tempfile::tempdir().unwrap();
+
+    let response = server(mock.url(), cache.path()).search_levels("mock", 0).await.unwrap();
+
+    assert_eq!(response.page_count, 1);
+    assert_eq!(response.items[0].name, LEVEL_NAME);
+    let request = mock.server.requests().into_iter().find(|r| r.path.starts_with("/sonolus/levels/list")).unwrap();
+    assert!(request.path.contains("keywords=mock"));
+}
+
+#[tokio::test]
+async fn computes_timings() {
+    let fixture = fixture_level().await;
+    let level = &fixture.level;
+
+    let timing = get_sound_timings(level, 0.0).await.unwrap();
+
+    assert_eq!(timing.single["#PERFECT"], vec![0.5, 1.0, 1.5, 2.5, 3.0]);
+    assert_eq!(timing.single["Sekai Critical Tap"], vec![2.0]);
+    assert_eq!(timing.connect["#HOLD"], vec![(2.5, 3.0)]);
+}
+
+#[tokio::test]
+async fn computes_level_stats() {
+    let fixture = fixture_level().await;
+    let level = &fixture.level;
+
+    let stats = LevelStats::from_level(level).await.unwrap();
+
+    assert_eq!(stats.notes, 6);
+    assert_eq!(stats.archetypes["NormalTapNote"], 3);
+    assert_eq!(stats.clips["#PERFECT"], 5);
+    assert_eq!(stats.clips["#HOLD"], 1);
+    assert_eq!(stats.bpm_changes.len(), 1);
+    assert_eq!(stats.duration, 3.0);
+    assert_eq!(stats.peak_nps, 2.0);
+    assert_eq!(stats.average_nps, 2.4);
+    assert_eq!(stats.slide_hold_time, 0.5);
+}
+
+#[tokio::test]
+async fn synthesizes_fixture_level() {
+    let fixture = fixture_level().await;
+    let level = &fixture.level;
+    let timing = get_sound_timings(level, 0.0).await.unwrap();
+
+    let sounds = collect(&timing, &test_effect()).await;
+
+    // #PERFECTは5ノーツを2ノーツ毎に分けるので3スレッド
+    assert_eq!(sounds.len(), 5);
+    let merged = sounds.values().fold(Sound::empty(None), |merged, sound| merged.overlay_at(sound, 0.0));
+    let last_note_end = 3 * 48000 * 2 + 4800;
+    assert!(merged.data.len() >= last_note_end - 2);
+    assert_eq!(merged.data[(2 * 48000) * 2], 100);
+}
+
+#[tokio::test]
+async fn handles_unknown_clips() {
+    let timing = Timing {
+        single: HashMap::from([("#PERFECT".to_string(), vec![0.0]), ("Custom Clip".to_string(), vec![0.5])]),
+        connect: HashMap::new(),
+    };
+    let effect = test_effect();
+
+    let err = synthesis(&timing, &effect, 2, UnknownClipPolicy::Fail).await.unwrap_err();
+    assert!(matches!(err, Error::UnknownClip { clip } if clip == "Custom Clip"));
+    let err = synthesis(&timing, &effect, 0, UnknownClipPolicy::Skip).await.unwrap_err();
+    assert!(matches!(err, Error::InvalidInput(_)), "{:?}", err);
+
+    for (policy, threads) in [(UnknownClipPolicy::Skip, 1), (UnknownClipPolicy::Substitute, 2)] {
+        let rx = synthesis(&timing, &effect, 2, policy).await.unwrap();
+        let Progress::Warning { clip, .. } = rx.recv().unwrap() else {
+            panic!("unknown clips should be reported before Info");
+        };
+        assert_eq!(clip, "Custom Clip");
+        let Progress::Info { threads: infos } = rx.recv().unwrap() else {
+            panic!("Info should follow the warnings");
+        };
+        assert_eq!(infos.len(), threads);
+        if policy == UnknownClipPolicy::Substitute {
+            // 表示用の名前が無いクリップはクリップ名で表示する
+            assert_eq!(infos["Custom Clip (1)"].clip, "Custom Clip");
+        }
+    }
+}
+
+#[tokio::test]
+async fn resolves_and_filters_time_range() {
+    let fixture = fixture_level().await;
+    let level = &fixture.level;
+    let timing = get_sound_timings(level, 0.0).await.unwrap();
+
+    // 120BPMなので、1小節目（4拍目）は2秒
+    let range = TimeRange {
+        from: Some(Position::Measure(0.5)),
+        to: Some(Position::Beat(5.5)),
+        pre_roll: 0.25,
+        post_roll: 0.0,
+    };
+    let window = range.resolve(&level.data).unwrap();
+    assert_eq!(
+        window,
+        Window {
+            start: 0.75,
+            end: Some(2.75)
+        }
+    );
+
+    let filtered = window.filter(&timing, &test_effect());
+    assert_eq!(filtered.single["#PERFECT"], vec![1.0, 1.5, 2.5]);
+    assert_eq!(filtered.single["Sekai Critical Tap"], vec![2.0]);
+    assert_eq!(filtered.connect["#HOLD"], vec![(2.5, 2.75)]);
+
+    let reversed = TimeRange {
+        from: Some(Position::Seconds(2.0)),
+        to: Some(Position::Seconds(1.0)),
+        ..Default::default()
+    };
+    assert!(reversed.resolve(&level.data).is_err());
+}
+
+#[test]
+fn trims_sound_to_window() {
+    let sound = Sound {
+        data: vec![1; 48000 * 2],
+        bitrate: 48000,
+    };
+    let window = Window {
+        start: -0.5,
+        end: Some(0.5),
+    };
+
+    let trimmed = window.trim(sound);
+
+    assert_eq!(trimmed.data.len(), 48000 * 2);
+    assert_eq!(trimmed.data[0], 0);
+    assert_eq!(trimmed.data[48000 + 1], 1);
+}
+
+#[tokio::test]
+async fn works_offline_from_cache() {
+    let fixture = fixture_level().await;
+    let mut bgm = vec![];
+    fixture.level.fetch_bgm(&mut bgm).await.unwrap();
+    let requests = fixture.mock.server.requests().len();
+
+    let level = offline(server(fixture.mock.url(), fixture.cache.path())).fetch_level(LEVEL_NAME).await.unwrap();
+    let mut offline_bgm = vec![];
+    level.fetch_bgm(&mut offline_bgm).await.unwrap();
+
+    assert_eq!(bgm, offline_bgm);
+    assert_eq!(fixture.mock.server.requests().len(), requests);
+}
+
+#[tokio::test]
+async fn offline_reports_missing_cache() {
+    set_lang(Lang::En);
+    let mock = MockSonolus::start().await;
+    let cache = tempfile::tempdir().unwrap();
+
+    let result = offline(server(mock.url(), cache.path())).fetch_level(LEVEL_NAME).await;
+
+    let Err(err) = result else {
+        panic!("offline fetch without cache should fail");
+    };
+    assert!(err.to_string().contains("is not cached"), "{}", err);
+    assert!(matches!(
+        err.root(),
+        Error::NotCached {
+            kind: CacheKind::LevelInfo,
+            ..
+        }
+    ));
+    assert!(mock.server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn rejects_corrupt_download() {
+    let mock = MockSonolus::start_with_hashes(
+        Fixtures::default(),
+        HashMap::from([("level_data", "0000000000000000000000000000000000000000".to_string())]),
+    )
+    .await;
+    let cache = tempfile::tempdir().unwrap();
+
+    let result = server(mock.url(), cache.path()).fetch_level(LEVEL_NAME).await;
+
+    assert!(matches!(result.err().unwrap().root(), Error::HashMismatch { .. }));
+    assert!(Cache::new(cache.path()).entries().await.unwrap().iter().all(|entry| entry.key.contains("level-")));
+}
+
+#[test]
+fn reports_missing_effect_archive_entry() {
+    let data = EffectData {
+        clips: vec![EffectClip {
+            name: "#PERFECT".to_string(),
+            filename: "missing.wav".to_string(),
+        }],
+    };
+    let zip = zip::ZipWriter::new(std::io::Cursor::new(vec![])).finish().unwrap();
+    let archive = zip::ZipArchive::new(std::io::Cursor::new(zip.into_inner())).unwrap();
+
+    let err = Effect::new(data, archive).unwrap_err();
+
+    assert!(matches!(err, Error::Decompress(_)), "{:?}", err);
+}
+
+#[tokio::test]
+async fn reports_corrupt_entity() {
+    set_lang(Lang::En);
+    let mut data = level_data();
+    data["entities"].as_array_mut().unwrap().push(serde_json::json!({
+        "archetype": "NormalTapNote",
+        "name": "broken",
+        "data": [],
+    }));
+    let fixture = fixture_level_with(Fixtures {
+        level_data: gzip(data.to_string().as_bytes()),
+        ..Fixtures::default()
+    })
+    .await;
+    let level = &fixture.level;
+
+    let err = get_sound_timings(level, 0.0).await.unwrap_err();
+
+    let Error::CorruptLevel {
+        archetype,
+        entity,
+        field,
+    } = &err
+    else {
+        panic!("unexpected error: {}", err);
+    };
+    assert_eq!(archetype, "NormalTapNote");
+    assert_eq!(entity.as_deref(), Some("broken"));
+    assert_eq!(field, "#BEAT");
+    assert_eq!(err.to_string(), "Level data is corrupt: NormalTapNote (broken) has no #BEAT");
+}
+
+#[tokio::test]
+async fn renders_fixture_level() {
+    if !has_ffmpeg() {
+        eprintln!("ffmpeg was not found; skipping");
+        return;
+    }
+    let fixture = fixture_level().await;
+    let output = tempfile::tempdir().unwrap();
+    let level = &fixture.level;
+
+    let prepared = pipeline::prepare(
+        level,
+        &PipelineOptions {
+            bgm: BgmSource::Level,
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    let sounds = collect(&prepared.timing, &prepared.effect).await;
+    let merged = sounds.values().fold(prepared.bgm.unwrap(), |merged, sound| merged.overlay_at(sound, 0.0));
+    let path = output.path().join("out.wav");
+    merged.export(path.to_str().unwrap(), &ExportSettings::new(Format::Wav)).unwrap();
+
+    assert!(std::fs::metadata(&path).unwrap().len() > 0);
+}
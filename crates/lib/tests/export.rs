@@ -0,0 +1,82 @@
+use pjsekai_soundgen_core::{
+    export::{ExportSettings, Format},
+    i18n::{set_lang, Lang},
+};
+
+#[test]
+fn guesses_format_from_path() {
+    let cases = [
+        ("out.mp3", Some(Format::Mp3)),
+        ("out.M4A", Some(Format::Aac)),
+        ("out.aac", Some(Format::Aac)),
+        ("dir.flac/out.ogg", Some(Format::Vorbis)),
+        ("out.opus", Some(Format::Opus)),
+        ("out.flac", Some(Format::Flac)),
+        ("out.wav", Some(Format::Wav)),
+        ("out.txt", None),
+        ("out", None),
+    ];
+    for (path, format) in cases {
+        assert_eq!(Format::from_path(path), format, "{}", path);
+    }
+    assert_eq!(ExportSettings::for_path("out").format, Format::Mp3);
+}
+
+#[test]
+fn validates_path_against_format() {
+    set_lang(Lang::En);
+    let cases = [
+        (Format::Mp3, "out.mp3", None),
+        (Format::Aac, "out.m4a", None),
+        (Format::Aac, "out.aac", None),
+        (Format::Flac, "out.FLAC", None),
+        (Format::Wav, "out", None),
+        (Format::Wav, "out.bin", None),
+        (
+            Format::Flac,
+            "out.mp3",
+            Some("The output extension does not match the format (flac): out.mp3 (use .flac)"),
+        ),
+        (Format::Aac, "out.ogg", Some("(use .m4a)")),
+        (Format::Opus, "out.ogg", Some("(use .opus)")),
+    ];
+    for (format, path, expected) in cases {
+        let result = ExportSettings::new(format).validate_path(path);
+        match expected {
+            None => assert!(result.is_ok(), "{} {}: {:?}", format, path, result),
+            Some(expected) => {
+                let error = result.unwrap_err().to_string();
+                assert!(error.contains(expected), "{} {}: {}", format, path, error);
+            }
+        }
+    }
+}
+
+#[test]
+fn validates_settings_combinations() {
+    set_lang(Lang::En);
+    let valid = [
+        ExportSettings::new(Format::Mp3).with_bitrate(128).with_sample_rate(44100),
+        ExportSettings::new(Format::Vorbis).with_quality(-1.0),
+        ExportSettings::new(Format::Flac).with_bit_depth(24).with_sample_rate(96000),
+        ExportSettings::new(Format::Wav).with_bit_depth(32),
+    ];
+    for settings in valid {
+        assert!(settings.validate().is_ok(), "{:?}", settings);
+    }
+
+    let invalid = [
+        (ExportSettings::new(Format::Mp3).with_bitrate(128).with_quality(2.0), "cannot be specified together"),
+        (ExportSettings::new(Format::Mp3).with_bitrate(512), "between 8k and 320k"),
+        (ExportSettings::new(Format::Flac).with_bitrate(320), "flac does not support a bitrate"),
+        (ExportSettings::new(Format::Opus).with_quality(5.0), "opus does not support a quality"),
+        (ExportSettings::new(Format::Aac).with_quality(3.0), "between 0.1 and 2"),
+        (ExportSettings::new(Format::Opus).with_sample_rate(44100), "opus cannot be written at 44100 Hz"),
+        (ExportSettings::new(Format::Mp3).with_bit_depth(16), "mp3 does not support a bit depth"),
+        (ExportSettings::new(Format::Flac).with_bit_depth(32), "must be one of 16, 24"),
+    ];
+    for (settings, expected) in invalid {
+        let error = settings.validate().unwrap_err().to_string();
+        assert!(error.contains(expected), "{:?}: {}", settings, error);
+    }
+}
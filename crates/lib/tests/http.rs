@@ -0,0 +1,93 @@
+mod common;
+
+use common::{MockServer, Response};
+use pjsekai_soundgen_core::http::{HttpClient, HttpConfig, USER_AGENT};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+fn fast_config() -> HttpConfig {
+    HttpConfig {
+        read_timeout: Duration::from_millis(300),
+        retry_delay: Duration::from_millis(10),
+        ..HttpConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn retries_server_errors() {
+    let count = AtomicUsize::new(0);
+    let server = MockServer::start(move |_| {
+        if count.fetch_add(1, Ordering::SeqCst) < 2 {
+            Response::status(503)
+        } else {
+            Response::ok("ok")
+        }
+    })
+    .await;
+    let client = HttpClient::new(fast_config()).unwrap();
+
+    let body = client.get(&format!("{}/data", server.url)).await.unwrap();
+
+    assert_eq!(body, b"ok");
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn gives_up_after_retries() {
+    let server = MockServer::start(|_| Response::status(500)).await;
+    let client = HttpClient::new(HttpConfig {
+        retries: 2,
+        ..fast_config()
+    })
+    .unwrap();
+
+    assert!(client.get(&format!("{}/data", server.url)).await.is_err());
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn does_not_retry_client_errors() {
+    let server = MockServer::start(|_| Response::status(404)).await;
+    let client = HttpClient::new(fast_config()).unwrap();
+
+    assert!(client.get(&format!("{}/data", server.url)).await.is_err());
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn times_out_stalled_body() {
+    let count = AtomicUsize::new(0);
+    let server = MockServer::start(move |_| {
+        if count.fetch_add(1, Ordering::SeqCst) == 0 {
+            Response {
+                stall: Some(Duration::from_secs(5)),
+                ..Response::ok("late")
+            }
+        } else {
+            Response::ok("ok")
+        }
+    })
+    .await;
+    let client = HttpClient::new(fast_config()).unwrap();
+
+    let body = client.get(&format!("{}/data", server.url)).await.unwrap();
+
+    assert_eq!(body, b"ok");
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn sends_user_agent_and_headers() {
+    let server = MockServer::start(|_| Response::ok("{}")).await;
+    let client = HttpClient::new(HttpConfig {
+        headers: vec![("X-Test".to_string(), "value".to_string())],
+        ..fast_config()
+    })
+    .unwrap();
+
+    client.get(&format!("{}/data", server.url)).await.unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!(request.header("user-agent"), Some(USER_AGENT));
+    assert_eq!(request.header("x-test"), Some("value"));
+}
@@ -0,0 +1,11 @@
+use pjsekai_soundgen_core::i18n::Lang;
+
+#[test]
+fn parses_languages() {
+    assert_eq!(Lang::from_locale("ja-JP"), Lang::Ja);
+    assert_eq!(Lang::from_locale("ja_JP.UTF-8"), Lang::Ja);
+    assert_eq!(Lang::from_locale("en-US"), Lang::En);
+    assert_eq!(Lang::from_locale("fr"), Lang::En);
+    assert_eq!("EN".parse::<Lang>().unwrap(), Lang::En);
+    assert!("fr".parse::<Lang>().is_err());
+}
@@ -0,0 +1,69 @@
+use pjsekai_soundgen_core::{
+    i18n::{set_lang, Lang},
+    identifier::LevelIdentifier,
+    registry::ServerRegistry,
+};
+
+fn parse(input: &str) -> pjsekai_soundgen_core::error::Result<LevelIdentifier> {
+    set_lang(Lang::En);
+    LevelIdentifier::parse(input, &ServerRegistry::default())
+}
+
+#[test]
+fn parses_level_identifiers() {
+    let cases = [
+        ("chcy-abc", "chcy-abc", None),
+        ("#chcy-abc", "chcy-abc", None),
+        ("  #chcy-abc\n", "chcy-abc", None),
+        ("https://cc.sevenc7c.com/charts/abc", "chcy-abc", Some("https://cc.sevenc7c.com")),
+        ("https://cc.sevenc7c.com/charts/chcy-abc", "chcy-abc", Some("https://cc.sevenc7c.com")),
+        ("cc.sevenc7c.com/charts/abc/", "chcy-abc", Some("https://cc.sevenc7c.com")),
+        (
+            "https://open.sonolus.com/cc.sevenc7c.com/levels/chcy-abc",
+            "chcy-abc",
+            Some("https://cc.sevenc7c.com"),
+        ),
+        ("https://sonolus.com/levels/chcy-abc", "chcy-abc", None),
+        ("sonolus://cc.sevenc7c.com/levels/chcy-abc", "chcy-abc", Some("https://cc.sevenc7c.com")),
+        ("https://example.com/sonolus/levels/foo", "foo", Some("https://example.com")),
+        ("https://example.com/api/sonolus/levels/foo", "foo", Some("https://example.com/api")),
+        ("http://localhost:3000/sonolus/levels/foo", "foo", Some("http://localhost:3000")),
+    ];
+    for (input, name, server_url) in cases {
+        let identifier = parse(input).unwrap_or_else(|e| panic!("{}: {}", input, e));
+        assert_eq!(
+            identifier,
+            LevelIdentifier {
+                name: name.to_string(),
+                server_url: server_url.map(str::to_string),
+            },
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn rejects_invalid_level_identifiers() {
+    let cases = [
+        ("", "The level ID is empty."),
+        ("#", "The level ID is empty."),
+        ("chcy abc", "Not a valid level ID: chcy abc"),
+        ("https://example.com/sonolus/levels/", "The URL does not contain a level ID"),
+        ("https://open.sonolus.com/cc.sevenc7c.com/levels", "The URL does not contain a level ID"),
+        ("https://open.sonolus.com/", "Not a valid level URL"),
+        ("https://cc.sevenc7c.com/", "The URL does not contain a level ID"),
+        ("https://example.com/charts/abc", "Not a valid level URL"),
+    ];
+    for (input, expected) in cases {
+        let error = parse(input).expect_err(input).to_string();
+        assert!(error.contains(expected), "{}: {}", input, error);
+    }
+}
+
+#[test]
+fn reports_unknown_server_with_level() {
+    let identifier = parse("xxxx-abc").unwrap();
+    let error = ServerRegistry::default().guess(&identifier.name).unwrap_err().to_string();
+    assert!(error.contains("xxxx-abc"), "{}", error);
+}
@@ -0,0 +1,15 @@
+use pjsekai_soundgen_core::{error::Error, registry::ServerRegistry};
+
+#[test]
+fn falls_back_to_defaults_only_when_missing() {
+    let dir = tempfile::tempdir().unwrap();
+    let registry = ServerRegistry::load_from(&dir.path().join("servers.toml")).unwrap();
+    assert_eq!(registry.servers.len(), ServerRegistry::default().servers.len());
+
+    let unreadable = [dir.path().to_path_buf(), dir.path().join("invalid.toml")];
+    std::fs::write(&unreadable[1], [0xff, 0xfe, 0x00]).unwrap();
+    for path in unreadable {
+        let err = ServerRegistry::load_from(&path).unwrap_err();
+        assert!(matches!(&err, Error::Config { path: error_path, .. } if error_path == &path), "{:?}", err);
+    }
+}
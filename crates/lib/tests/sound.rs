@@ -0,0 +1,62 @@
+use pjsekai_soundgen_core::{
+    export::{ExportSettings, Format},
+    sound::{Effect, Sound},
+    Error,
+};
+
+#[test]
+fn round_trips_effect_pcm() {
+    let effect = Effect {
+        audio: [("#PERFECT", 48000), ("Sekai Critical Tap", 44100), ("#HOLD", 48000)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, bitrate))| {
+                let data = (0..100).map(|j| (i as i16 * 1000 - j) * if j % 2 == 0 { 1 } else { -1 }).collect();
+                (name.to_string(), Sound { data, bitrate })
+            })
+            .collect(),
+    };
+
+    let bytes = effect.to_pcm();
+    let restored = Effect::from_pcm(&bytes).unwrap();
+
+    assert_eq!(restored.audio.len(), effect.audio.len());
+    for (name, sound) in effect.audio.iter() {
+        let restored = &restored.audio[name];
+        assert_eq!(restored.data, sound.data, "{}", name);
+        assert_eq!(restored.bitrate, sound.bitrate, "{}", name);
+    }
+    assert!(Effect::from_pcm(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Effect::from_pcm(b"not pcm").is_err());
+}
+
+/// 標準エラー出力に大量に書き込んでから失敗する、偽のffmpegを使います。
+#[cfg(unix)]
+#[test]
+fn reports_ffmpeg_errors_without_blocking() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let ffmpeg = dir.path().join("ffmpeg");
+    std::fs::write(
+        &ffmpeg,
+        "#!/bin/sh\nhead -c 1000000 /dev/zero | tr '\\0' x >&2\necho >&2\necho 'fake error' >&2\nexit 1\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let mut paths = vec![dir.path().to_path_buf()];
+    paths.extend(std::env::split_paths(&path));
+    std::env::set_var("PATH", std::env::join_paths(paths).unwrap());
+
+    let sound = Sound {
+        data: vec![0; 1_000_000],
+        bitrate: 48000,
+    };
+    let output = dir.path().join("out.wav");
+    let err = sound.export(output.to_str().unwrap(), &ExportSettings::new(Format::Wav)).unwrap_err();
+    assert!(matches!(&err, Error::AudioEncode(message) if message == "fake error"), "{:?}", err);
+
+    let err = Sound::load(&vec![0; 1_000_000]).unwrap_err();
+    assert!(matches!(&err, Error::AudioDecode(message) if message == "fake error"), "{:?}", err);
+}
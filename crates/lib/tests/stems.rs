@@ -0,0 +1,83 @@
+use pjsekai_soundgen_core::{
+    i18n::{set_lang, Lang},
+    sound::Sound,
+    stems::StemGrouping,
+};
+use std::collections::HashMap;
+
+fn sound(data: &[i16]) -> Sound {
+    Sound {
+        data: data.to_vec(),
+        bitrate: 48000,
+    }
+}
+
+fn clips(clips: &[(&str, &[i16])]) -> HashMap<String, Sound> {
+    clips.iter().map(|(name, data)| (name.to_string(), sound(data))).collect()
+}
+
+fn stems(stems: Vec<(String, Sound)>) -> Vec<(String, Vec<i16>)> {
+    stems.into_iter().map(|(name, sound)| (name, sound.data)).collect()
+}
+
+#[test]
+fn splits_clips_into_category_stems() {
+    let clips = clips(&[
+        ("#PERFECT", &[1, 1]),
+        ("Sekai Critical Tap", &[2, 2, 2, 2]),
+        ("Sekai Tick", &[5]),
+        ("Custom Clip", &[7, 7]),
+    ]);
+
+    let stems = stems(StemGrouping::category().split(clips, Some(sound(&[9; 6]))));
+
+    assert_eq!(
+        stems,
+        [
+            ("taps".to_string(), vec![3, 3, 2, 2, 0, 0]),
+            ("flicks".to_string(), vec![0; 6]),
+            ("traces".to_string(), vec![0; 6]),
+            ("ticks".to_string(), vec![5, 0, 0, 0, 0, 0]),
+            ("holds".to_string(), vec![0; 6]),
+            ("other".to_string(), vec![7, 7, 0, 0, 0, 0]),
+            ("bgm".to_string(), vec![9; 6]),
+        ]
+    );
+}
+
+#[test]
+fn splits_critical_clips_separately() {
+    let clips = clips(&[("#PERFECT", &[1, 1]), ("Sekai Critical Tap", &[2, 2, 2, 2])]);
+
+    let stems = stems(StemGrouping::critical().split(clips, None));
+
+    assert_eq!(stems.len(), StemGrouping::critical().groups.len());
+    assert_eq!(stems[0], ("taps".to_string(), vec![1, 1, 0, 0]));
+    assert_eq!(stems[1], ("critical-taps".to_string(), vec![2, 2, 2, 2]));
+    assert!(stems[2..].iter().all(|(_, data)| data == &[0; 4]));
+}
+
+#[test]
+fn loads_custom_grouping() {
+    set_lang(Lang::En);
+    let grouping =
+        StemGrouping::from_toml("[[groups]]\nname = \"notes\"\nclips = [\"#PERFECT\", \"Sekai Critical Tap\"]\n")
+            .unwrap();
+    assert_eq!(grouping.stem_of("Sekai Critical Tap"), "notes");
+    assert_eq!(grouping.stem_of("Sekai Tick"), "other");
+    assert_eq!("category".parse::<StemGrouping>().unwrap(), StemGrouping::category());
+
+    let cases = [
+        ("[[groups]]\nname = \"bgm\"\nclips = []\n", "The stem name bgm is reserved."),
+        ("[[groups]]\nname = \"other\"\nclips = []\n", "The stem name other is reserved."),
+        (
+            "[[groups]]\nname = \"a\"\nclips = []\n[[groups]]\nname = \"a\"\nclips = []\n",
+            "Duplicate stem name: a",
+        ),
+        ("[[groups]]\nname = 1\n", "Invalid stem settings"),
+    ];
+    for (content, expected) in cases {
+        let error = StemGrouping::from_toml(content).unwrap_err().to_string();
+        assert!(error.contains(expected), "{}: {}", content, error);
+    }
+}
@@ -0,0 +1,9 @@
+use pjsekai_soundgen_core::tempo::Position;
+
+#[test]
+fn parses_positions() {
+    assert_eq!("12.5".parse::<Position>().unwrap(), Position::Seconds(12.5));
+    assert_eq!("b:32".parse::<Position>().unwrap(), Position::Beat(32.0));
+    assert_eq!("m:8".parse::<Position>().unwrap(), Position::Measure(8.0));
+    assert!("x:1".parse::<Position>().is_err());
+}